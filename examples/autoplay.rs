@@ -0,0 +1,76 @@
+//! Lets the autopilot play a level many times without a window, to find levels
+//! that are too hard or impossible to finish.
+//!
+//! ```
+//! cargo run --example autoplay --no-default-features -- assets/levels/classic.txt --strategy greedy --games 20
+//! ```
+
+use rust_snake::ai::{Autopilot, Strategy};
+use rust_snake::engine::Game;
+use rust_snake::level::Level;
+
+
+/// Time passed to `make_step`, small enough that every call moves the snakes at most one cell.
+const FRAME_TIME: f64 = 0.05;
+
+fn usage() -> ! {
+    eprintln!("usage: autoplay [level] [--strategy greedy|hamiltonian|survival] [--games N] [--steps N]");
+    std::process::exit(1);
+}
+
+fn main() {
+    let mut level_path = None;
+    let mut strategy = Strategy::Greedy;
+    let mut games = 10;
+    let mut max_steps = 10_000;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--strategy" => strategy = args.next().and_then(|s| Strategy::from_name(&s)).unwrap_or_else(|| usage()),
+            "--games" => games = args.next().and_then(|s| s.parse().ok()).unwrap_or_else(|| usage()),
+            "--steps" => max_steps = args.next().and_then(|s| s.parse().ok()).unwrap_or_else(|| usage()),
+            _ => level_path = Some(arg),
+        }
+    }
+
+    let level = match level_path {
+        Some(path) => Level::from_file(&path).unwrap_or_else(|err| {
+            eprintln!("Failed to load level {}: {}", path, err);
+            std::process::exit(1);
+        }),
+        None => Level::classic(20, 20),
+    };
+
+    println!("Playing {} games of \"{}\" with the {} strategy", games, level.name, strategy.name());
+
+    let mut total_score = 0;
+    let mut survived = 0;
+    for seed in 0..games {
+        let mut game = Game::with_seed(&level, seed);
+        let mut autopilots: Vec<Autopilot> = (0..game.snakes.len()).map(|i| Autopilot::new(i, strategy)).collect();
+
+        while !game.is_over() && game.step_count() < max_steps {
+            for autopilot in &mut autopilots {
+                autopilot.update(&mut game);
+            }
+            game.make_step(FRAME_TIME);
+        }
+
+        let scores: Vec<String> = game.snakes.iter().map(|s| s.score().to_string()).collect();
+        let outcome = match (game.is_won(), game.is_over()) {
+            (true, _) => "won",
+            (false, true) => "over",
+            (false, false) => "still running",
+        };
+        println!("seed {}: {} after {} steps, scores {}", seed, outcome, game.step_count(), scores.join(" / "));
+
+        total_score += game.snakes.iter().map(|s| s.score()).sum::<usize>();
+        if !game.is_over() {
+            survived += 1;
+        }
+    }
+
+    println!("average score {:.1}, {} of {} games reached {} steps",
+             total_score as f64 / (games as f64 * level.snakes.len() as f64), survived, games, max_steps);
+}
@@ -0,0 +1,325 @@
+//! Computer controlled snakes.
+//!
+//! An `Autopilot` steers one snake through `Game::change_dir`, exactly like a player would,
+//! so its games are recorded and replayed like any other. Plans look ahead on the grid as the
+//! engine will see it: doors open and close on their timers, teleporters move the head, and
+//! cells occupied by a snake become free once its tail has passed them. Doors which would close
+//! on the body before the tail has passed them are not entered.
+
+use std::collections::VecDeque;
+use std::iter;
+
+use array2d::Array2D;
+
+use crate::engine::{Direction, Game, Point2i, TailRule};
+use crate::field::{ClosingRule, HeadProgress};
+
+
+const DIRECTIONS: [Direction; 4] = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Strategy {
+    /// Shortest path to the food as long as enough room is left afterwards,
+    /// otherwise the Hamiltonian cycle or the largest open area.
+    Greedy,
+    /// Follows a cycle through the free 2x2 blocks of the board, which never runs into
+    /// the own tail but takes long to reach the food. Food off the cycle is fetched greedily.
+    Hamiltonian,
+    /// Always moves towards the largest open area, ignoring the food unless it is on the way.
+    Survival,
+}
+
+impl Strategy {
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::Greedy => "greedy",
+            Strategy::Hamiltonian => "hamiltonian",
+            Strategy::Survival => "survival",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Strategy> {
+        match name {
+            "greedy" => Some(Strategy::Greedy),
+            "hamiltonian" => Some(Strategy::Hamiltonian),
+            "survival" => Some(Strategy::Survival),
+            _ => None,
+        }
+    }
+}
+
+
+/// The board as seen from the current grid step. Step 1 is the next move.
+struct Plan<'a> {
+    game: &'a Game,
+    /// The last step in which a cell is still occupied by a snake.
+    blocked_until: Array2D<usize>,
+    /// Length of the planning snake, the number of steps a cell stays covered after the head entered it.
+    length: usize,
+}
+
+impl<'a> Plan<'a> {
+    fn new(game: &'a Game, player: usize) -> Plan<'a> {
+        let mut blocked_until = Array2D::filled_with(0, game.height() as usize, game.width() as usize);
+        let leaving = match game.tail() {
+            TailRule::Follow => 0,
+            TailRule::Block => 1,
+        };
+
+        for snake in game.snakes.iter().filter(|s| s.is_alive()) {
+            // a growing snake keeps its tail in place until it reached its length
+            let growth = snake.length().saturating_sub(snake.body.len());
+            let cells = snake.body.iter().map(|segment| segment.0).chain(iter::once(snake.head()));
+
+            // segment i leaves the board in step i + 1 + growth, a blocking tail still counts in that step
+            for (i, cell) in cells.enumerate() {
+                let until = &mut blocked_until[cell.as_coords()];
+                *until = (*until).max(i + leaving + growth);
+            }
+        }
+
+        Plan {
+            game,
+            blocked_until,
+            // the body grows by one for every food eaten on the way, assume the next piece is eaten too
+            length: game.snakes[player].length() + 1,
+        }
+    }
+
+    /// Whether a door on `cell` closes on the body of a snake whose head enters it in step `step`.
+    /// Waiting doors stay open until the snake has passed.
+    fn closes_on_body(&self, cell: Point2i, step: usize) -> bool {
+        match self.game.field.closing_rule(cell) {
+            Some(ClosingRule::Cut) | Some(ClosingRule::Kill) => {
+                // the cell is covered until the tail leaves it in the move after step `step + length`,
+                // the door ticks before that move
+                (step + 1..=step + self.length + 1).any(|later| self.game.field.predict_blocking(cell, later))
+            }
+            _ => false,
+        }
+    }
+
+    /// Where the head ends up when moving from `from` in direction `dir` in step `step`,
+    /// along with the direction it faces afterwards. `None` if the snake would die.
+    fn advance(&self, from: Point2i, dir: Direction, step: usize) -> Option<(Point2i, Direction)> {
+        let next = self.game.next_cell(from, dir)?;
+        let (target, dir) = match self.game.field.predict_enter(next, dir, step) {
+            HeadProgress::Pass | HeadProgress::Trigger(_) => (next, dir),
+            HeadProgress::Collide => return None,
+            HeadProgress::Redirect { to, dir: new_dir } => {
+                if self.game.field.predict_blocking(to, step) {
+                    return None;
+                }
+                (to, new_dir.unwrap_or(dir))
+            }
+        };
+
+        // poison is not deadly, but never worth it
+        let poisoned = self.game.food.iter().any(|f| f.position == target && !f.kind.is_wanted());
+
+        if step <= self.blocked_until[target.as_coords()] || poisoned || self.closes_on_body(target, step) {
+            None
+        } else {
+            Some((target, dir))
+        }
+    }
+
+    /// Breadth first search from `start`, facing `dir`, after `step` steps.
+    /// Returns the earliest step every cell can be reached in, with the first direction taken.
+    fn reach(&self, start: Point2i, dir: Direction, step: usize) -> Array2D<Option<(usize, Direction)>> {
+        let mut reached = Array2D::filled_with(None, self.game.height() as usize, self.game.width() as usize);
+        let mut queue = VecDeque::new();
+        queue.push_back((start, dir, step, None));
+
+        while let Some((cell, facing, step, first)) = queue.pop_front() {
+            // the engine ignores turning around, so the search does too
+            for dir in DIRECTIONS.iter().copied().filter(|d| *d != facing.opposite()) {
+                if let Some((next, next_dir)) = self.advance(cell, dir, step + 1) {
+                    if reached[next.as_coords()].is_none() {
+                        let first = first.unwrap_or(dir);
+                        reached[next.as_coords()] = Some((step + 1, first));
+                        queue.push_back((next, next_dir, step + 1, Some(first)));
+                    }
+                }
+            }
+        }
+
+        reached
+    }
+
+    /// The first direction towards the closest wanted food which can be reached before it disappears.
+    fn towards_food(&self, head: Point2i, facing: Direction) -> Option<Direction> {
+        let reached = self.reach(head, facing, 0);
+        let now = self.game.step_count();
+
+        self.game.food.iter()
+            .filter(|food| food.kind.is_wanted())
+            .filter_map(|food| {
+                reached[food.position.as_coords()].filter(|(step, _)| food.expires_at.is_none_or(|at| now + step < at))
+            })
+            .min_by_key(|(step, _)| *step)
+            .map(|(_, dir)| dir)
+    }
+
+    /// Number of cells reachable after moving from `head` in direction `dir`, `None` if the move kills the snake.
+    fn room_after(&self, head: Point2i, dir: Direction) -> Option<usize> {
+        let (next, next_dir) = self.advance(head, dir, 1)?;
+        let reached = self.reach(next, next_dir, 1);
+        Some(reached.elements_row_major_iter().filter(|r| r.is_some()).count())
+    }
+}
+
+
+/// A cycle through every cell of the 2x2 blocks which contain no field elements and are connected
+/// to the block of `start`. It runs counter-clockwise around a spanning tree of these blocks.
+/// Maps every cell on the cycle to the direction of the next one, `None` if `start` is not in such a block.
+fn hamiltonian_cycle(game: &Game, start: Point2i) -> Option<Array2D<Option<Direction>>> {
+    let mut cycle = Array2D::filled_with(None, game.height() as usize, game.width() as usize);
+    let (blocks_x, blocks_y) = (game.width() / 2, game.height() / 2);
+
+    let usable = |bx: i32, by: i32| {
+        (0..blocks_x).contains(&bx) && (0..blocks_y).contains(&by)
+            && (0..4).all(|i| game.field.element_at(Point2i::new(2 * bx + i % 2, 2 * by + i / 2)).is_none())
+    };
+
+    let root = (start.x / 2, start.y / 2);
+    if !usable(root.0, root.1) {
+        return None;
+    }
+
+    let mut in_tree = Array2D::filled_with(false, blocks_y as usize, blocks_x as usize);
+    let mut edges = Vec::new();
+    let mut queue = VecDeque::new();
+    in_tree[(root.1 as usize, root.0 as usize)] = true;
+    queue.push_back(root);
+
+    while let Some((bx, by)) = queue.pop_front() {
+        let (x, y) = (2 * bx, 2 * by);
+        cycle[(y as usize, x as usize)] = Some(Direction::Down);
+        cycle[(y as usize + 1, x as usize)] = Some(Direction::Right);
+        cycle[(y as usize + 1, x as usize + 1)] = Some(Direction::Up);
+        cycle[(y as usize, x as usize + 1)] = Some(Direction::Left);
+
+        for dir in DIRECTIONS.iter() {
+            let (nx, ny) = (bx + dir.vector().x, by + dir.vector().y);
+            if usable(nx, ny) && !in_tree[(ny as usize, nx as usize)] {
+                in_tree[(ny as usize, nx as usize)] = true;
+                edges.push(((bx, by), (nx, ny)));
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+
+    // every tree edge joins the loops around its two blocks
+    for (a, b) in edges {
+        let (low, high) = if a.0 + a.1 < b.0 + b.1 { (a, b) } else { (b, a) };
+        let (lx, ly, hx, hy) = (2 * low.0 as usize, 2 * low.1 as usize, 2 * high.0 as usize, 2 * high.1 as usize);
+        if low.1 == high.1 {
+            cycle[(ly + 1, lx + 1)] = Some(Direction::Right);
+            cycle[(hy, hx)] = Some(Direction::Left);
+        } else {
+            cycle[(ly + 1, lx)] = Some(Direction::Down);
+            cycle[(hy, hx + 1)] = Some(Direction::Up);
+        }
+    }
+
+    Some(cycle)
+}
+
+
+/// Steers one snake of a game.
+pub struct Autopilot {
+    player: usize,
+    strategy: Strategy,
+    cycle: Option<Array2D<Option<Direction>>>,
+    decided_at: Option<usize>,
+}
+
+impl Autopilot {
+    pub fn new(player: usize, strategy: Strategy) -> Autopilot {
+        Autopilot {
+            player,
+            strategy,
+            cycle: None,
+            decided_at: None,
+        }
+    }
+
+    pub fn player(&self) -> usize {
+        self.player
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    /// Lets the autopilot turn the snake if needed, has to be called before every `make_step`.
+    /// It decides once per grid step.
+    pub fn update(&mut self, game: &mut Game) {
+        if self.decided_at == Some(game.step_count()) {
+            return;
+        }
+        self.decided_at = Some(game.step_count());
+
+        if let Some(dir) = self.choose(game) {
+            if dir != game.snakes[self.player].dir() {
+                game.change_dir(self.player, dir);
+            }
+        }
+    }
+
+    /// The direction the snake should move in on the next grid step, `None` if it is dead
+    /// or every direction is fatal.
+    pub fn choose(&mut self, game: &Game) -> Option<Direction> {
+        let snake = &game.snakes[self.player];
+        if !snake.is_alive() {
+            return None;
+        }
+
+        let plan = Plan::new(game, self.player);
+        let head = snake.head();
+        let length = snake.length();
+
+        let has_room = |dir: &Direction| plan.room_after(head, *dir).is_some_and(|room| room >= length);
+        let towards_food = || plan.towards_food(head, snake.dir()).filter(has_room);
+
+        match self.strategy {
+            Strategy::Greedy => {
+                towards_food()
+                    .or_else(|| self.along_cycle(&plan, head).filter(has_room))
+                    .or_else(|| Autopilot::survive(&plan, head, snake.dir()))
+            }
+            Strategy::Hamiltonian => {
+                let along_cycle = self.along_cycle(&plan, head).filter(|dir| plan.advance(head, *dir, 1).is_some());
+                // food outside of the cycle would never be eaten
+                let food_on_cycle = self.cycle.as_ref().is_some_and(|cycle| {
+                    game.food.iter().any(|food| food.kind.is_wanted() && cycle[food.position.as_coords()].is_some())
+                });
+
+                if food_on_cycle { along_cycle } else { towards_food().or(along_cycle) }
+                    .or_else(|| Autopilot::survive(&plan, head, snake.dir()))
+            }
+            Strategy::Survival => Autopilot::survive(&plan, head, snake.dir()),
+        }
+    }
+
+    /// The next direction on the Hamiltonian cycle, if the head is on it and can follow it.
+    fn along_cycle(&mut self, plan: &Plan, head: Point2i) -> Option<Direction> {
+        let game = plan.game;
+        if self.cycle.is_none() {
+            self.cycle = hamiltonian_cycle(game, head);
+        }
+        self.cycle.as_ref()?[head.as_coords()].filter(|dir| *dir != game.snakes[self.player].dir().opposite())
+    }
+
+    /// The direction leading to the most room, the one closer to the food on a tie.
+    fn survive(plan: &Plan, head: Point2i, facing: Direction) -> Option<Direction> {
+        let towards_food = plan.towards_food(head, facing);
+
+        DIRECTIONS.iter().copied()
+            .filter(|dir| *dir != facing.opposite())
+            .filter_map(|dir| plan.room_after(head, dir).map(|room| (dir, room)))
+            .max_by_key(|(dir, room)| (*room, towards_food == Some(*dir)))
+            .map(|(dir, _)| dir)
+    }
+}
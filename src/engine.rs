@@ -1,21 +1,27 @@
-extern crate piston_window;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
-use piston_window::keyboard::Key;
+use crate::field::{Appearance, ClosingRule, Door, GameField, HeadProgress, Trigger, Wall, FOOD_CHANNEL};
+use crate::food::{Effect, Food, FoodKind};
+use crate::level::Level;
+use crate::occupancy::Occupancy;
+use crate::replay::{Replay, ReplayInput};
+use crate::save::SaveGame;
+use crate::snake::Snake;
 
-use std::collections::VecDeque;
-use std::collections::HashSet;
-use rand::Rng;
-use array2d::Array2D;
 
+/// Free cells drawn for new food before falling back to collecting every cell it may go to.
+const SPAWN_DRAWS: usize = 8;
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub struct Point2<T> {
     pub x: T,
     pub y: T,
 }
 
-type Point2i = Point2<i32>;
-type Point2f = Point2<f32>;
+pub type Point2i = Point2<i32>;
+pub type Point2f = Point2<f32>;
 
 impl<T> Point2<T> {
     pub fn new(x: T, y: T) -> Self {
@@ -33,365 +39,872 @@ impl Point2<i32> {
 }
 
 
-struct DirBuffer {
-    data: VecDeque<Point2i>,
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
 }
 
-impl DirBuffer {
-    pub fn new() -> DirBuffer {
-        DirBuffer {
-            data: VecDeque::new(),
+impl Direction {
+    pub fn vector(self) -> Point2i {
+        match self {
+            Direction::Up => Point2i::new(0, -1),
+            Direction::Down => Point2i::new(0, 1),
+            Direction::Left => Point2i::new(-1, 0),
+            Direction::Right => Point2i::new(1, 0),
         }
     }
 
-    pub fn enqueue_dir(&mut self, dir: Point2i) {
-        if !self.data.contains(&dir) {
-            self.data.push_back(dir);
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::Left => "left",
+            Direction::Right => "right",
         }
     }
 
-    pub fn next_dir(&mut self) -> Option<Point2i> {
-        self.data.pop_front()
+    pub fn from_name(name: &str) -> Option<Direction> {
+        match name {
+            "up" => Some(Direction::Up),
+            "down" => Some(Direction::Down),
+            "left" => Some(Direction::Left),
+            "right" => Some(Direction::Right),
+            _ => None,
+        }
     }
-}
 
+    pub fn from_vector(v: Point2i) -> Option<Direction> {
+        match (v.x, v.y) {
+            (0, -1) => Some(Direction::Up),
+            (0, 1) => Some(Direction::Down),
+            (-1, 0) => Some(Direction::Left),
+            (1, 0) => Some(Direction::Right),
+            _ => None,
+        }
+    }
 
-#[derive(Hash, Eq, PartialEq)]
-pub struct Teleporter {
-    pub start: Point2i,
-    pub end: Point2i,
-}
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
 
-impl Teleporter {
-    pub fn new(start: Point2i, end: Point2i) -> Teleporter {
-        Teleporter {
-            start,
-            end,
+    /// The direction after a quarter turn counter-clockwise, as seen on the screen.
+    pub fn turn_left(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Left,
+            Direction::Left => Direction::Down,
+            Direction::Down => Direction::Right,
+            Direction::Right => Direction::Up,
         }
     }
-}
 
+    pub fn turn_right(self) -> Direction {
+        self.turn_left().opposite()
+    }
+}
 
-pub struct Door {
-    pub cells: HashSet<Point2i>,
-    period: usize,
-    pub open: bool,
-    current_count: usize,
+/// What happens when the snake head leaves the board.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BorderRule {
+    /// The border is a wall.
+    Solid,
+    /// The head enters the board again on the opposite side.
+    Wrap,
+    /// The snake turns around, its tail becomes the new head.
+    Bounce,
 }
 
-impl Door {
-    pub fn new(cells: HashSet<Point2i>, period: usize) -> Door {
-        Door {
-            cells,
-            period,
-            open: false,
-            current_count: period,
+impl BorderRule {
+    pub fn name(self) -> &'static str {
+        match self {
+            BorderRule::Solid => "solid",
+            BorderRule::Wrap => "wrap",
+            BorderRule::Bounce => "bounce",
         }
     }
 
-    pub fn tick(&mut self) {
-        if self.current_count > 0 {
-            self.current_count -= 1;
-        } else {
-            self.current_count = self.period;
-            self.open = !self.open;
+    pub fn from_name(name: &str) -> Option<BorderRule> {
+        match name {
+            "solid" => Some(BorderRule::Solid),
+            "wrap" => Some(BorderRule::Wrap),
+            "bounce" => Some(BorderRule::Bounce),
+            _ => None,
         }
     }
 }
 
-pub enum GameEvent {
-    None,
-    Turn,
-    Collision,
-    Teleport,
-    Eat,
+/// Whether a head may move into the cell a tail leaves in the same grid step.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TailRule {
+    /// The tail moves on first, so snakes can chase their own or another snake's tail.
+    Follow,
+    /// The tail still blocks its cell during the step it leaves it.
+    Block,
 }
 
-/*
+impl TailRule {
+    pub fn name(self) -> &'static str {
+        match self {
+            TailRule::Follow => "follow",
+            TailRule::Block => "block",
+        }
+    }
 
-enum HeadProgress {
-    SnakeHead(Point2d),
-    Collision
+    pub fn from_name(name: &str) -> Option<TailRule> {
+        match name {
+            "follow" => Some(TailRule::Follow),
+            "block" => Some(TailRule::Block),
+            _ => None,
+        }
+    }
 }
 
-trait GameElement {
-    fn handle(&self, head: Point2d) -> HeadProgress;
+/// How the time the snakes need to move by one cell changes during a game.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SpeedCurve {
+    /// Seconds per grid step at the start of the game.
+    pub start: f32,
+    /// Factor applied to the step time for every piece of food eaten.
+    pub per_food: f32,
+    /// Factor applied to the step time for every minute played.
+    pub per_minute: f32,
+    /// The shortest step time the curve reaches.
+    pub min: f32,
 }
 
-impl GameElement for Teleporter {
-    fn handle(&self, head: Point2d) -> HeadProgress {
-        return HeadProgress::SnakeHead(self.end);
+impl SpeedCurve {
+    pub const PRESETS: [(&'static str, SpeedCurve); 4] = [
+        ("easy", SpeedCurve { start: 0.35, per_food: 0.99, per_minute: 0.95, min: 0.15 }),
+        ("normal", SpeedCurve { start: 0.3, per_food: 0.98, per_minute: 0.9, min: 0.1 }),
+        ("hard", SpeedCurve { start: 0.2, per_food: 0.97, per_minute: 0.85, min: 0.07 }),
+        ("insane", SpeedCurve { start: 0.12, per_food: 0.96, per_minute: 0.8, min: 0.04 }),
+    ];
+
+    pub fn preset(name: &str) -> Option<SpeedCurve> {
+        SpeedCurve::PRESETS.iter().find(|(n, _)| *n == name).map(|(_, curve)| *curve)
     }
-}
 
-impl GameElement for Door {
-    fn handle(&self, head: Point2d) -> HeadProgress {
-        if self.open {
-            return HeadProgress::Collision;
-        }
-        return HeadProgress::SnakeHead(head);
+    /// The name of the preset this curve is equal to.
+    pub fn preset_name(&self) -> Option<&'static str> {
+        SpeedCurve::PRESETS.iter().find(|(_, curve)| curve == self).map(|(n, _)| *n)
     }
-}
 
-struct Wall {
-}
+    /// The preset after this one, for cycling through them in a menu. Custom curves continue with the first preset.
+    pub fn next_preset(&self) -> SpeedCurve {
+        let index = SpeedCurve::PRESETS.iter().position(|(_, curve)| curve == self).map_or(0, |i| i + 1);
+        SpeedCurve::PRESETS[index % SpeedCurve::PRESETS.len()].1
+    }
 
-impl GameElement for Wall {
-    fn handle(&self, head: Point2d) -> HeadProgress {
-        return HeadProgress::Collision;
+    /// The step time after a grid step of `step_time` seconds in which the food in `eaten` was eaten.
+    pub fn next_step_time(&self, step_time: f32, eaten: &[FoodKind]) -> f32 {
+        let ramp = self.per_minute.powf(step_time / 60.0);
+        let food = self.per_food.powi(eaten.len() as i32);
+        (step_time * ramp * food).max(self.min)
     }
 }
 
-
-struct GameField<'a> {
-    width: i32,
-    height: i32,
-    cells: Array2D<Option<&'a dyn GameElement>>,
-    teleporters: HashSet<Teleporter>,
-    doors: Vec<Door>,
-    walls: Vec<Wall>,
+impl Default for SpeedCurve {
+    fn default() -> SpeedCurve {
+        SpeedCurve::PRESETS[1].1
+    }
 }
 
-impl<'a> GameField<'a> {
-    pub fn new(width: i32,
-               height: i32) -> GameField<'a> {
-        let mut field = GameField {
-            width,
-            height,
-            cells: Array2D::filled_with(None, height as usize, width as usize),
-            teleporters: Default::default(),
-            doors: vec![],
-            walls: vec![]
-        };
+/// Everything a player, bot or replay can tell the game, independent of any input backend.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Input {
+    Turn(Direction),
+}
 
-        for y in 0..field.height {
-            if !(5..8).contains(&y) {
-                field.walls.push(Wall {});
-                field.cells[(y as usize, (field.width / 2) as usize)] = Some(&field.walls[field.walls.len()-1]);
-            }
-        }
 
-        return field;
-    }
+/// Why a snake died.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DeathCause {
+    /// Left the board with a solid border.
+    Border,
+    /// Crashed into a wall, a closed door or another blocking element.
+    Obstacle,
+    OwnBody,
+    /// Ran into the body of another snake.
+    Snake(usize),
+    /// Met the head of another snake that was at least as long.
+    HeadOn(usize),
+    /// A door closed on the snake.
+    Door,
 }
 
-*/
+/// Something that happened during a grid step. A single step can cause any number of events.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum GameEvent {
+    Turn { player: usize, dir: Direction },
+    /// The snake hit a bouncing border and turned around.
+    Bounce { player: usize },
+    Teleport { player: usize, from: Point2i, to: Point2i },
+    Eat { player: usize, at: Point2i, kind: FoodKind, length: usize },
+    /// Special food disappeared without being eaten.
+    FoodExpired { at: Point2i, kind: FoodKind },
+    /// `door` is the index of the door in the game field.
+    DoorToggled { door: usize, open: bool },
+    /// A closing door cut off the tail of the snake at `at`, leaving it with `length`.
+    Cut { player: usize, at: Point2i, length: usize },
+    /// No free cell is left for new food, which ends the game as a victory.
+    BoardFull,
+    Death { player: usize, cause: DeathCause },
+}
 
 pub struct Game {
     width: i32,
     height: i32,
-    pub snake_body: VecDeque<(Point2i, Point2i)>,
-    snake_front: Point2i,
-    current_length: usize,
-    pub food_location: Point2i,
-    dir_x: i32,
-    dir_y: i32,
+    pub snakes: Vec<Snake>,
+    pub food: Vec<Food>,
+    /// Effects of eaten food which are still going on.
+    effects: Vec<Effect>,
+    border: BorderRule,
+    tail: TailRule,
+    speed: SpeedCurve,
     snake_progress: f32,
     snake_step_time: f32,
-    rng: rand::rngs::ThreadRng,
-    dir_buffer: DirBuffer,
-    pub game_field: Array2D<u32>,
-    pub teleporters: HashSet<Teleporter>,
-    pub doors: Vec<Door>,
+    /// Seconds played, stops counting when the game is over.
+    play_time: f64,
+    /// A paused game neither moves nor takes inputs.
+    paused: bool,
+    seed: u64,
+    rng: ChaCha8Rng,
+    step_count: usize,
+    recording: Option<Replay>,
+    /// Set when no food could be placed anymore.
+    board_full: bool,
+    level: Level,
+    pub field: GameField,
+    /// Which snake covers which cell, kept up to date with every move.
+    occupancy: Occupancy,
 }
 
 impl Game {
-    pub fn new(width: i32, height: i32) -> Game {
+    pub fn from_level(level: &Level) -> Game {
+        Game::with_seed(level, rand::random())
+    }
+
+    /// Creates a game whose food placement is fully determined by `seed`,
+    /// so the same seed and the same inputs always result in the same game.
+    /// There is one snake for every start position of the level.
+    pub fn with_seed(level: &Level, seed: u64) -> Game {
         let mut game = Game {
-            width: width as i32,
-            height: height as i32,
-            snake_body: VecDeque::new(),
-            snake_front: Point2::new(width / 4, height / 2),
-            current_length: 5,
-            food_location: Point2i::new(1, 1),
-            dir_x: 0,
-            dir_y: 1,
+            width: level.width,
+            height: level.height,
+            snakes: level.snakes.iter().map(|s| Snake::new(s.start, s.dir, s.length)).collect(),
+            food: Vec::new(),
+            effects: Vec::new(),
+            border: level.border,
+            tail: level.tail,
+            speed: level.speed,
             snake_progress: 0.0,
-            snake_step_time: 0.3,
-            rng: rand::thread_rng(),
-            dir_buffer: DirBuffer::new(),
-            game_field: Array2D::filled_with(0, height as usize, width as usize),
-            teleporters: HashSet::new(),
-            doors: Vec::new(),
+            snake_step_time: level.speed.start,
+            play_time: 0.0,
+            paused: false,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            step_count: 0,
+            recording: None,
+            board_full: false,
+            level: level.clone(),
+            field: GameField::new(level.width, level.height),
+            occupancy: Occupancy::new(level.width, level.height, |_| false),
         };
-        for y in 0..game.height {
-            if !(5..8).contains(&y) {
-                game.game_field[(y as usize, (game.width / 2) as usize)] = 1
+
+        game.field.add(Box::new(Wall::new(level.walls.clone())));
+        // door i listens on channel i + 1, channel 0 is the food channel
+        for (i, door) in level.doors.iter().enumerate() {
+            game.field.add(Box::new(Door::new(door.cells.iter().copied().collect(), door.control.clone(), door.closing, i as u32 + 1)));
+        }
+        for teleporter in &level.teleporters {
+            let mut teleporter = teleporter.clone();
+            teleporter.reseed(seed);
+            game.field.add(Box::new(teleporter));
+        }
+        for trigger in &level.triggers {
+            game.field.add(Box::new(Trigger::new(trigger.kind, trigger.cells.clone(), trigger.door as u32 + 1)));
+        }
+        let occupancy = Occupancy::new(level.width, level.height, |p| game.may_hold_food(p));
+        game.occupancy = occupancy;
+        game.occupancy.fill(&game.snakes, &game.food);
+
+        match level.food {
+            Some(position) => {
+                game.food.push(Food { kind: FoodKind::Normal, position, expires_at: None });
+                game.occupancy.add_food(position);
             }
+            None => game.board_full = !game.spawn_food(FoodKind::Normal),
         }
 
-        game.insert_teleporter_2way(Point2i::new(game.width - 1, game.height - 1), Point2i::new(0, 0));
-
-        game.doors.push(Door::new(
-            Iterator::collect(IntoIterator::into_iter([Point2i::new(game.width / 2, 5), Point2i::new(game.width / 2, 6), Point2i::new(game.width / 2, 7)])),
-            5));
+        game
+    }
 
+    /// Continues a saved game. It is not recorded, replays always start at the beginning of a game.
+    pub fn from_save(save: &SaveGame) -> Game {
+        let mut game = Game::with_seed(&save.level, save.seed);
+        game.rng.set_word_pos(save.rng_position);
+        game.step_count = save.step_count;
+        game.snake_progress = save.progress;
+        game.snake_step_time = save.step_time;
+        game.play_time = save.play_time;
+        game.food = save.food.clone();
+        game.effects = save.effects.clone();
+        game.snakes = save.snakes.iter().map(Snake::from_state).collect();
+        game.occupancy.fill(&game.snakes, &game.food);
+        game.field.restore_states(&save.elements);
         game
     }
 
-    pub fn handle_key(&mut self, key: piston_window::Key)
-    {
-        match key {
-            Key::Left => self.change_dir(-1, 0),
-            Key::Right => self.change_dir(1, 0),
-            Key::Up => self.change_dir(0, -1),
-            Key::Down => self.change_dir(0, 1),
-            _ => ()
-        }
-    }
-
-    pub fn get_interpolated_snake_head(&self) -> Point2f {
-        let dir_x = self.dir_x as f32;
-        let dir_y = self.dir_y as f32;
-        // self.snake_front represents the next position on grid
-        // when progress will hit 100%
-        // until then we need to interpolate between last position
-        // and snake_front
-        let progress = self.snake_progress-1.0;
-
-        Point2f::new(self.snake_front.x as f32 + dir_x * progress,
-                     self.snake_front.y as f32 + dir_y * progress,
-        )
-    }
-
-    pub fn get_interpolated_snake_tail(&self) -> Option<Point2f> {
-        self.snake_body.front().map(|tail| {
-            let dir_x = tail.1.x as f32;
-            let dir_y = tail.1.y as f32;
-            // self.snake_front represents the next position on grid
-            // when progress will hit 100%
-            // until then we need to interpolate between last position
-            // and snake_front
-            let progress = if self.snake_body.len() == self.current_length {
-                self.snake_progress
-            } else {
-                0.0
-            };
+    /// The level the game was created from, with the border and tail rules in effect.
+    pub fn level(&self) -> Level {
+        let mut level = self.level.clone();
+        level.border = self.border;
+        level.tail = self.tail;
+        level
+    }
 
-            Point2f::new(tail.0.x as f32 + dir_x * progress,
-                         tail.0.y as f32 + dir_y * progress,
-            )
-        })
+    /// Everything needed to continue the game later with `from_save`.
+    pub fn save_state(&self) -> SaveGame {
+        SaveGame {
+            level: self.level(),
+            seed: self.seed,
+            rng_position: self.rng.get_word_pos(),
+            step_count: self.step_count,
+            progress: self.snake_progress,
+            step_time: self.snake_step_time,
+            play_time: self.play_time,
+            food: self.food.clone(),
+            effects: self.effects.clone(),
+            snakes: self.snakes.iter().map(|s| s.state()).collect(),
+            elements: self.field.states(),
+            // the game doesn't know who steers the snakes
+            autopilots: Vec::new(),
+        }
     }
 
-    fn change_dir(&mut self, dir_x: i32, dir_y: i32) {
-        self.dir_buffer.enqueue_dir(Point2i::new(dir_x, dir_y));
+    pub fn handle_input(&mut self, player: usize, input: Input) {
+        match input {
+            Input::Turn(dir) => self.change_dir(player, dir),
+        }
     }
 
-    fn teleport_if_needed(&self, front: Point2i, event: &mut GameEvent) -> Point2i {
-        for t in &self.teleporters {
-            if front == t.start
-            {
-                *event = GameEvent::Teleport;
-                return t.end;
-            }
+    pub fn get_interpolated_snake_head(&self, player: usize) -> Point2f {
+        self.snakes[player].interpolated_head(self.snake_progress)
+    }
+
+    pub fn get_interpolated_snake_tail(&self, player: usize) -> Option<Point2f> {
+        self.snakes[player].interpolated_tail(self.snake_progress)
+    }
+
+    /// Queues a turn of `player`'s snake. Paused games ignore it, like any other input.
+    pub fn change_dir(&mut self, player: usize, dir: Direction) {
+        if player >= self.snakes.len() || self.paused {
+            return;
         }
 
-        front
+        if let Some(replay) = &mut self.recording {
+            replay.inputs.push(ReplayInput::Turn(player, dir));
+        }
+
+        self.snakes[player].enqueue_dir(dir);
     }
 
-    pub fn current_length(&self) -> usize {
-        self.current_length
+    /// Records every following step and direction change into a replay of the level of the game.
+    /// Has to be called right after creating the game, before the first `make_step`.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Replay::new(self.seed, self.level()));
     }
 
-    fn do_snake_step(&mut self, dx: i32, dy: i32, event: &mut GameEvent) -> bool {
-        self.snake_body.push_back((self.snake_front, Point2i::new(self.dir_x, self.dir_y)));
+    pub fn take_recording(&mut self) -> Option<Replay> {
+        self.recording.take()
+    }
 
-        let mut next_front = self.snake_front;
-        next_front.x = std::cmp::min(std::cmp::max(0, next_front.x + dx), self.width - 1);
-        next_front.y = std::cmp::min(std::cmp::max(0, next_front.y + dy), self.height - 1);
+    /// Number of grid steps the snakes have moved so far.
+    pub fn step_count(&self) -> usize {
+        self.step_count
+    }
 
-        if !self.cell_is_free(next_front) {
-            eprintln!("Snake collision!");
-            return false;
+    /// Seconds played so far.
+    pub fn play_time(&self) -> f64 {
+        self.play_time
+    }
+
+    /// Stops the game until `resume` is called. The snakes stay exactly where they are, including
+    /// their position between two cells, so the game continues smoothly afterwards.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// A single player game is over when the snake died, a multiplayer game
+    /// when at most one snake is left. Any game is over when the board is full or the time is up.
+    pub fn is_over(&self) -> bool {
+        if self.board_full || self.is_time_up() {
+            return true;
         }
 
-        self.snake_front = self.teleport_if_needed(next_front, event);
+        let alive = self.snakes.iter().filter(|s| s.is_alive()).count();
+        if self.snakes.len() > 1 {
+            alive <= 1
+        } else {
+            alive == 0
+        }
+    }
 
-        if self.snake_front == self.food_location {
-            self.current_length += 1;
-            *event = GameEvent::Eat;
-            
-            loop {
-                self.food_location.x = self.rng.gen_range(0..self.width);
-                self.food_location.y = self.rng.gen_range(0..self.height);
+    /// Whether the snakes filled the board, so that no food can be placed anymore,
+    /// or a snake survived until the end of a timed game.
+    pub fn is_won(&self) -> bool {
+        self.board_full || self.is_time_up() && self.snakes.iter().any(|s| s.is_alive())
+    }
 
-                if self.cell_is_free(self.food_location) {
-                    break;
-                }
-            }
+    /// Seconds until a timed game ends, `None` if the level has no time limit.
+    pub fn time_left(&self) -> Option<f64> {
+        self.level.time_limit.map(|limit| (limit as f64 - self.play_time).max(0.0))
+    }
+
+    pub fn is_time_up(&self) -> bool {
+        self.time_left() == Some(0.0)
+    }
+
+    /// The winner of a finished multiplayer game, `None` for a draw.
+    /// If the last snakes die in the same step, the longest of them wins.
+    /// On a full board or when the time is up the surviving snake with the highest score wins.
+    pub fn winner(&self) -> Option<usize> {
+        if self.snakes.len() < 2 || !self.is_over() {
+            return None;
         }
 
-        while self.snake_body.len() > self.current_length {
-            self.snake_body.pop_front();
+        if self.board_full || self.is_time_up() {
+            let alive = || (0..self.snakes.len()).filter(|i| self.snakes[*i].is_alive());
+            let best = alive().map(|i| self.snakes[i].score()).max()?;
+            let mut best = alive().filter(|i| self.snakes[*i].score() == best);
+            return match (best.next(), best.next()) {
+                (Some(winner), None) => Some(winner),
+                _ => None,
+            };
         }
 
-        true
+        if let Some(survivor) = self.snakes.iter().position(|s| s.is_alive()) {
+            return Some(survivor);
+        }
+
+        let last_step = self.snakes.iter().filter_map(|s| s.died_at()).max();
+        let last_dead: Vec<usize> = (0..self.snakes.len())
+            .filter(|i| self.snakes[*i].died_at() == last_step)
+            .collect();
+        let longest = last_dead.iter().map(|i| self.snakes[*i].length()).max()?;
+        let mut best = last_dead.into_iter().filter(|i| self.snakes[*i].length() == longest);
+
+        match (best.next(), best.next()) {
+            (Some(winner), None) => Some(winner),
+            _ => None,
+        }
     }
 
-    fn cell_is_free(&self, p: Point2i) -> bool {
-        if p == self.snake_front { return false; }
-        for sp in self.snake_body.iter() {
-            if p == (*sp).0 {
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Which living snake covers which cell.
+    pub fn occupancy(&self) -> &Occupancy {
+        &self.occupancy
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn border(&self) -> BorderRule {
+        self.border
+    }
+
+    pub fn set_border(&mut self, border: BorderRule) {
+        self.border = border;
+    }
+
+    pub fn tail(&self) -> TailRule {
+        self.tail
+    }
+
+    pub fn set_tail(&mut self, tail: TailRule) {
+        self.tail = tail;
+    }
+
+    pub fn speed(&self) -> SpeedCurve {
+        self.speed
+    }
+
+    /// Seconds the snakes currently need to move by one cell, with the effects of eaten food.
+    pub fn step_time(&self) -> f32 {
+        self.effects.iter().map(|effect| effect.kind.step_time_factor()).product::<f32>() * self.snake_step_time
+    }
+
+    pub fn effects(&self) -> &[Effect] {
+        &self.effects
+    }
+
+    pub fn contains(&self, p: Point2i) -> bool {
+        (0..self.width).contains(&p.x) && (0..self.height).contains(&p.y)
+    }
+
+    /// The cell reached by moving from `p` in direction `dir`, `None` if this leaves the board.
+    pub fn next_cell(&self, p: Point2i, dir: Direction) -> Option<Point2i> {
+        let next = Point2i::new(p.x + dir.vector().x, p.y + dir.vector().y);
+
+        match self.border {
+            BorderRule::Wrap => Some(Point2i::new(next.x.rem_euclid(self.width), next.y.rem_euclid(self.height))),
+            _ if self.contains(next) => Some(next),
+            _ => None,
+        }
+    }
+
+    /// Whether food may ever be placed on `p`, the level has to allow it and an element there has to
+    /// carry the food on. Which elements do that doesn't change during a game.
+    fn may_hold_food(&self, p: Point2i) -> bool {
+        self.level.allows_food(p) && (self.field.element_at(p).is_none() || self.field.carry_food(p).is_some())
+    }
+
+    /// The cell food dropped on `p` ends up in, `None` if food can't be placed there.
+    /// Food never lands on field elements unless they carry it on to a free cell.
+    fn food_target(&self, p: Point2i) -> Option<Point2i> {
+        let target = match self.field.element_at(p) {
+            Some(_) => self.field.carry_food(p)?,
+            None => p,
+        };
+        Some(target).filter(|t| self.cell_is_free(*t) && !self.occupancy.has_food(*t))
+    }
+
+    /// Places food of `kind` on a cell picked uniformly from the free cells the level allows food in.
+    /// Returns false if there is no such cell.
+    fn spawn_food(&mut self, kind: FoodKind) -> bool {
+        // food drawn onto a teleporter is carried to its exit, which may be taken, then we draw again
+        let mut position = None;
+        for _ in 0..SPAWN_DRAWS {
+            if self.occupancy.free_count() == 0 {
                 return false;
             }
+            let drawn = self.occupancy.nth_free(self.rng.gen_range(0..self.occupancy.free_count()));
+            position = self.food_target(drawn);
+            if position.is_some() {
+                break;
+            }
+        }
+        if position.is_none() {
+            let candidates: Vec<Point2i> = self.occupancy.free_cells().filter_map(|p| self.food_target(p)).collect();
+            position = candidates.choose(&mut self.rng).copied();
+        }
+
+        match position {
+            Some(position) => {
+                let expires_at = kind.lifetime().map(|lifetime| self.step_count + lifetime);
+                self.food.push(Food { kind, position, expires_at });
+                self.occupancy.add_food(position);
+                true
+            }
+            None => false,
         }
+    }
 
-        if self.game_field[p.as_coords()] > 0 {
+    /// Replaces eaten normal food and maybe adds a special kind next to it.
+    /// Returns false if the board is full.
+    fn respawn_food(&mut self) -> bool {
+        if !self.spawn_food(FoodKind::Normal) {
             return false;
         }
 
-        for door in &self.doors {
-            if !door.open {
-                for cell in &door.cells {
-                    if p == *cell {
-                        return false;
-                    }
+        let kind = *FoodKind::ALL.choose_weighted(&mut self.rng, |kind| kind.spawn_weight())
+            .unwrap_or(&FoodKind::Normal);
+        if kind != FoodKind::Normal {
+            self.spawn_food(kind);
+        }
+        true
+    }
+
+    /// Removes special food whose lifetime is over.
+    fn expire_food(&mut self, events: &mut Vec<GameEvent>) {
+        let step = self.step_count;
+        let occupancy = &mut self.occupancy;
+        self.food.retain(|food| {
+            let expired = food.expires_at.is_some_and(|at| at <= step);
+            if expired {
+                occupancy.remove_food(food.position);
+                events.push(GameEvent::FoodExpired { at: food.position, kind: food.kind });
+            }
+            !expired
+        });
+    }
+
+    /// Moves the head of snake `i` into its body and determines the cell it moves to
+    /// according to the border and the field elements. Channels of the triggers it enters are added
+    /// to `triggered`, they are signalled once every head moved.
+    fn advance_head(&mut self, i: usize, triggered: &mut Vec<u32>, events: &mut Vec<GameEvent>) -> Result<Point2i, DeathCause> {
+        let snake = &self.snakes[i];
+        if self.border == BorderRule::Bounce && self.next_cell(snake.head(), snake.dir()).is_none() {
+            self.snakes[i].reverse();
+            events.push(GameEvent::Bounce { player: i });
+        }
+
+        self.snakes[i].push_head();
+        self.occupancy.add(self.snakes[i].head(), i);
+
+        let dir = self.snakes[i].dir();
+        let next_front = self.next_cell(self.snakes[i].head(), dir).ok_or(DeathCause::Border)?;
+
+        match self.field.on_enter(next_front, dir) {
+            HeadProgress::Pass => self.field.entered(next_front),
+            HeadProgress::Collide => return Err(DeathCause::Obstacle),
+            HeadProgress::Redirect { to, dir } => {
+                self.field.entered(next_front);
+                if let Some(dir) = dir {
+                    self.snakes[i].set_dir(dir);
                 }
+                events.push(GameEvent::Teleport { player: i, from: next_front, to });
+
+                if self.field.is_blocking(to) {
+                    return Err(DeathCause::Obstacle);
+                }
+                return Ok(to);
+            }
+            HeadProgress::Trigger(channel) => {
+                self.field.entered(next_front);
+                triggered.push(channel);
             }
         }
 
-        // should we also check for food?
+        Ok(next_front)
+    }
 
-        true
+    /// How many tail segments every snake leaves in this step, before the heads arrive.
+    /// None unless heads may follow tails, and none for a snake growing from the food at its target.
+    fn vacated_tails(&self, targets: &[Option<Point2i>]) -> Vec<usize> {
+        self.snakes.iter().zip(targets).map(|(snake, target)| match (self.tail, target) {
+            (TailRule::Follow, Some(target)) => {
+                let growth = self.food.iter().find(|f| f.position == *target).map_or(0, |f| f.kind.growth());
+                let length = (snake.length() as i32 + growth).max(1) as usize;
+                snake.body.len().saturating_sub(length)
+            }
+            _ => 0,
+        }).collect()
     }
 
-    pub fn make_step(&mut self, dt: f64) -> GameEvent {
-        let mut event = GameEvent::None;
+    /// What kills snake `i` when moving to `target`, another snake or itself.
+    /// Heads meeting each other are won by the longer snake, equal lengths kill both.
+    fn collides(&self, i: usize, target: Point2i, old_heads: &[Point2i], targets: &[Option<Point2i>],
+                vacated: &[usize]) -> Option<DeathCause> {
+        (0..self.snakes.len()).filter(|j| self.snakes[*j].is_alive()).find_map(|j| {
+            let head_on = j != i && (targets[j] == Some(target)
+                || (target == old_heads[j] && targets[j] == Some(old_heads[i])));
+            let leaving = || self.snakes[j].body.iter().take(vacated[j]).any(|segment| segment.0 == target);
+
+            if head_on {
+                Some(DeathCause::HeadOn(j)).filter(|_| self.snakes[i].length() <= self.snakes[j].length())
+            } else if self.occupancy.snake_at(target) == Some(j) && !leaving() {
+                Some(if j == i { DeathCause::OwnBody } else { DeathCause::Snake(j) })
+            } else {
+                None
+            }
+        })
+    }
 
-        self.snake_progress += dt as f32 / self.snake_step_time;
-        if self.snake_progress < 1.0 {
-            return GameEvent::None; // still in microstepping
+    fn move_snakes(&mut self, events: &mut Vec<GameEvent>) {
+        let old_heads: Vec<Point2i> = self.snakes.iter().map(|s| s.head()).collect();
+
+        let mut triggered = Vec::new();
+        let advanced: Vec<Option<Result<Point2i, DeathCause>>> = (0..self.snakes.len()).map(|i| {
+            if self.snakes[i].is_alive() {
+                Some(self.advance_head(i, &mut triggered, events))
+            } else {
+                None
+            }
+        }).collect();
+        // doors see every head that entered them in this step, no matter which snake moved first
+        for channel in triggered {
+            let changed = self.field.trigger(channel);
+            self.report_doors(changed, events);
+        }
+        let targets: Vec<Option<Point2i>> = advanced.iter().map(|a| a.and_then(Result::ok)).collect();
+        let vacated = self.vacated_tails(&targets);
+
+        let causes: Vec<Option<DeathCause>> = advanced.iter().enumerate().map(|(i, advanced)| match advanced {
+            Some(Ok(target)) => self.collides(i, *target, &old_heads, &targets, &vacated),
+            Some(Err(cause)) => Some(*cause),
+            None => None,
+        }).collect();
+
+        let mut eaten_normal = 0;
+        for (i, target) in targets.iter().enumerate() {
+            if let Some(cause) = causes[i] {
+                self.occupancy.remove_snake(&self.snakes[i]);
+                self.snakes[i].kill(self.step_count);
+                events.push(GameEvent::Death { player: i, cause });
+            } else if let Some(target) = *target {
+                self.occupancy.remove(self.snakes[i].head());
+                self.snakes[i].move_to(target);
+                self.occupancy.add(target, i);
+                if let Some(index) = self.food.iter().position(|f| f.position == target) {
+                    let kind = self.food.swap_remove(index).kind;
+                    self.occupancy.remove_food(target);
+                    self.snakes[i].eat(kind);
+                    if let Some(steps) = kind.effect_steps() {
+                        // eating the same kind again makes the effect last longer, not stronger
+                        self.effects.retain(|effect| effect.kind != kind);
+                        self.effects.push(Effect { kind, ends_at: self.step_count + steps });
+                    }
+                    events.push(GameEvent::Eat { player: i, at: target, kind, length: self.snakes[i].length() });
+                    let changed = self.field.trigger(FOOD_CHANNEL);
+                    self.report_doors(changed, events);
+                    if kind == FoodKind::Normal {
+                        eaten_normal += 1;
+                    }
+                }
+            }
         }
 
-        self.snake_progress -= 1.0;
+        if !self.is_over() {
+            for _ in 0..eaten_normal {
+                if !self.respawn_food() {
+                    self.board_full = true;
+                    events.push(GameEvent::BoardFull);
+                    break;
+                }
+            }
+        }
 
-        for door in &mut self.doors {
-            door.tick();
+        for snake in &mut self.snakes {
+            for cell in snake.trim_tail() {
+                self.occupancy.remove(cell);
+            }
         }
+    }
 
-        match self.dir_buffer.next_dir() {
-            Some(dir) => {
-                // prevent self collision by pressing reverse direction
-                if self.dir_x != dir.x && self.dir_y != dir.y {
-                    self.dir_x = dir.x;
-                    self.dir_y = dir.y;
-                    event = GameEvent::Turn;
-                }
+    /// Adds a `DoorToggled` event for every door among the changed field elements.
+    fn report_doors(&self, changed: Vec<usize>, events: &mut Vec<GameEvent>) {
+        for door in changed {
+            if let Some(Appearance::Door { open, .. }) = self.field.appearance_of(door) {
+                events.push(GameEvent::DoorToggled { door, open });
             }
-            None => ()
         }
+    }
 
+    /// Applies the closing rule of the elements that block cells covered by a snake,
+    /// which happens when a door closes on it.
+    fn crush_snakes(&mut self, events: &mut Vec<GameEvent>) {
+        for i in 0..self.snakes.len() {
+            if !self.snakes[i].is_alive() {
+                continue;
+            }
 
-        if self.do_snake_step(self.dir_x, self.dir_y, &mut event) {
-            event
-        } else {
-            GameEvent::Collision
+            let head = self.snakes[i].head();
+            if self.field.is_blocking(head) {
+                match self.field.closing_rule(head) {
+                    // heads entering a waiting door hold it open, see `Door::entered`
+                    Some(ClosingRule::Wait) => debug_assert!(false, "a waiting door closed on a head at {:?}", head),
+                    // cutting at the head leaves nothing of the snake either
+                    _ => {
+                        self.occupancy.remove_snake(&self.snakes[i]);
+                        self.snakes[i].die(self.step_count);
+                        events.push(GameEvent::Death { player: i, cause: DeathCause::Door });
+                    }
+                }
+                continue;
+            }
+
+            // the segment closest to the head decides, everything behind it is lost anyway
+            let crushed = self.snakes[i].body.iter().rev().map(|segment| segment.0).find(|p| self.field.is_blocking(*p));
+            if let Some(at) = crushed {
+                match self.field.closing_rule(at) {
+                    Some(ClosingRule::Kill) => {
+                        self.occupancy.remove_snake(&self.snakes[i]);
+                        self.snakes[i].die(self.step_count);
+                        events.push(GameEvent::Death { player: i, cause: DeathCause::Door });
+                    }
+                    Some(ClosingRule::Cut) => {
+                        for cell in self.snakes[i].cut(at) {
+                            self.occupancy.remove(cell);
+                        }
+                        events.push(GameEvent::Cut { player: i, at, length: self.snakes[i].length() });
+                    }
+                    // waiting doors stay open while a snake covers them
+                    Some(ClosingRule::Wait) => debug_assert!(false, "a waiting door closed on a snake at {:?}", at),
+                    None => (),
+                }
+            }
         }
     }
 
-    fn insert_teleporter_2way(&mut self, p1: Point2i, p2: Point2i) {
-        self.teleporters.insert(Teleporter::new(p1, p2));
-        self.teleporters.insert(Teleporter::new(p2, p1));
+    fn cell_is_free(&self, p: Point2i) -> bool {
+        self.occupancy.snake_at(p).is_none() && !self.field.is_blocking(p)
+    }
+
+    /// Advances the game by `dt` seconds and returns everything that happened,
+    /// which is nothing unless the snakes moved on to the next cell. Paused games don't advance.
+    pub fn make_step(&mut self, dt: f64) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+
+        if self.is_over() || self.paused {
+            return events;
+        }
+
+        if let Some(replay) = &mut self.recording {
+            replay.inputs.push(ReplayInput::Step(dt));
+        }
+
+        self.play_time += dt;
+        if self.is_time_up() {
+            return events;
+        }
+        self.snake_progress += dt as f32 / self.step_time();
+        if self.snake_progress < 1.0 {
+            return events; // still in microstepping
+        }
+
+        self.snake_progress -= 1.0;
+        self.step_count += 1;
+
+        let changed = self.field.tick();
+        self.report_doors(changed, &mut events);
+
+        for (i, snake) in self.snakes.iter_mut().enumerate() {
+            if snake.is_alive() && snake.apply_next_dir() {
+                events.push(GameEvent::Turn { player: i, dir: snake.dir() });
+            }
+        }
+
+        self.expire_food(&mut events);
+        let step = self.step_count;
+        self.effects.retain(|effect| effect.ends_at > step);
+        self.move_snakes(&mut events);
+
+        // pressure plates react to the snakes in their new position
+        let occupancy = &self.occupancy;
+        let changed = self.field.after_move(&|p| occupancy.snake_at(p).is_some());
+        self.report_doors(changed, &mut events);
+        self.crush_snakes(&mut events);
+
+        let eaten: Vec<FoodKind> = events.iter().filter_map(|e| match e {
+            GameEvent::Eat { kind, .. } => Some(*kind),
+            _ => None,
+        }).collect();
+        self.snake_step_time = self.speed.next_step_time(self.snake_step_time, &eaten);
+
+        events
     }
-}
\ No newline at end of file
+}
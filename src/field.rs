@@ -0,0 +1,737 @@
+//! Static and dynamic elements of the playing field.
+//!
+//! Every non-empty cell of the board belongs to a `GameElement`. The element decides
+//! what happens when the snake head enters one of its cells, so new kinds of tiles
+//! can be added by implementing the trait without touching the game logic.
+
+use array2d::Array2D;
+
+use crate::engine::{Direction, Point2i};
+
+
+pub enum HeadProgress {
+    /// The head enters the cell as if it was empty.
+    Pass,
+    /// The snake crashes into the element.
+    Collide,
+    /// The head is moved to another cell, optionally facing a new direction.
+    Redirect { to: Point2i, dir: Option<Direction> },
+    /// The head enters the cell and `channel` is signalled to every element on the field.
+    Trigger(u32),
+}
+
+/// Signalled whenever a snake eats a piece of food.
+pub const FOOD_CHANNEL: u32 = 0;
+
+/// Doors about to close report it this many steps in advance, so frontends can warn the players.
+pub const DOOR_WARNING_STEPS: usize = 3;
+
+/// How a frontend should draw a cell of an element.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Appearance {
+    Wall,
+    /// `closing` is set while an open door is about to close.
+    Door { open: bool, closing: bool },
+    /// `exit_only` cells of one-way teleporters don't teleport, `active` is unset during the cooldown.
+    Teleporter { link: usize, exit_only: bool, active: bool },
+    Switch,
+    Plate { pressed: bool },
+    /// Keys stay on the field after they were picked up, but are no longer drawn.
+    Key { taken: bool },
+}
+
+pub trait GameElement {
+    /// Cells occupied by this element, they must not change over time.
+    fn cells(&self) -> &[Point2i];
+
+    /// What happens when the head moves onto `cell` in direction `dir`.
+    fn on_enter(&self, cell: Point2i, dir: Direction) -> HeadProgress;
+
+    /// Whether `cell` can't be occupied right now, neither by the snake nor by food.
+    fn is_blocking(&self, cell: Point2i) -> bool;
+
+    fn appearance(&self, cell: Point2i) -> Appearance;
+
+    /// `on_enter` as it will be answered after `steps` more calls of `tick`, used for planning ahead.
+    fn predict_enter(&self, cell: Point2i, dir: Direction, _steps: usize) -> HeadProgress {
+        self.on_enter(cell, dir)
+    }
+
+    /// `is_blocking` as it will be answered after `steps` more calls of `tick`.
+    fn predict_blocking(&self, cell: Point2i, _steps: usize) -> bool {
+        self.is_blocking(cell)
+    }
+
+    /// Everything about the element that changes during a game, for save games.
+    /// Elements that never change have no state.
+    fn state(&self) -> Vec<usize> {
+        Vec::new()
+    }
+
+    /// Restores a state returned by `state`.
+    fn set_state(&mut self, _state: &[usize]) {}
+
+    /// Called after the head actually entered `cell`.
+    fn entered(&mut self, _cell: Point2i) {}
+
+    /// Where food placed on `cell` ends up, `None` if it stays there.
+    fn carry_food(&self, _cell: Point2i) -> Option<Point2i> {
+        None
+    }
+
+    /// What happens to a snake covering a cell of the element when the element starts blocking it.
+    fn closing_rule(&self) -> ClosingRule {
+        ClosingRule::Kill
+    }
+
+    /// Number of grid steps until the element changes on its own, `None` if it only
+    /// changes in reaction to the snakes or never.
+    fn next_change(&self) -> Option<usize> {
+        None
+    }
+
+    /// Called once per grid step before the snake moves, returns whether the element changed.
+    fn tick(&mut self) -> bool {
+        false
+    }
+
+    /// Called once per grid step after the snakes moved, `occupied` tells whether a snake covers a cell.
+    /// Returns a channel to signal to every element on the field.
+    fn after_move(&mut self, _occupied: &dyn Fn(Point2i) -> bool) -> Option<u32> {
+        None
+    }
+
+    /// Called for every signalled channel, returns whether the element changed.
+    fn on_trigger(&mut self, _channel: u32) -> bool {
+        false
+    }
+}
+
+
+pub struct GameField {
+    cells: Array2D<Option<usize>>,
+    elements: Vec<Box<dyn GameElement>>,
+}
+
+impl GameField {
+    pub fn new(width: i32, height: i32) -> GameField {
+        GameField {
+            cells: Array2D::filled_with(None, height as usize, width as usize),
+            elements: Vec::new(),
+        }
+    }
+
+    /// Adds an element to the field. If cells are shared with an element added before,
+    /// the new element takes them over.
+    pub fn add(&mut self, element: Box<dyn GameElement>) -> usize {
+        let index = self.elements.len();
+        for cell in element.cells() {
+            self.cells[cell.as_coords()] = Some(index);
+        }
+        self.elements.push(element);
+        index
+    }
+
+    pub fn element_at(&self, p: Point2i) -> Option<&dyn GameElement> {
+        self.cells[p.as_coords()].map(|index| self.elements[index].as_ref())
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item=&dyn GameElement> {
+        self.elements.iter().map(|e| e.as_ref())
+    }
+
+    /// All occupied cells along with their appearance.
+    pub fn cells(&self) -> impl Iterator<Item=(Point2i, Appearance)> + '_ {
+        self.elements().flat_map(|e| e.cells().iter().map(move |cell| (*cell, e.appearance(*cell))))
+    }
+
+    pub fn on_enter(&self, p: Point2i, dir: Direction) -> HeadProgress {
+        self.element_at(p).map_or(HeadProgress::Pass, |e| e.on_enter(p, dir))
+    }
+
+    pub fn entered(&mut self, p: Point2i) {
+        if let Some(index) = self.cells[p.as_coords()] {
+            self.elements[index].entered(p);
+        }
+    }
+
+    pub fn is_blocking(&self, p: Point2i) -> bool {
+        self.element_at(p).is_some_and(|e| e.is_blocking(p))
+    }
+
+    pub fn carry_food(&self, p: Point2i) -> Option<Point2i> {
+        self.element_at(p).and_then(|e| e.carry_food(p))
+    }
+
+    pub fn closing_rule(&self, p: Point2i) -> Option<ClosingRule> {
+        self.element_at(p).map(|e| e.closing_rule())
+    }
+
+    pub fn predict_enter(&self, p: Point2i, dir: Direction, steps: usize) -> HeadProgress {
+        self.element_at(p).map_or(HeadProgress::Pass, |e| e.predict_enter(p, dir, steps))
+    }
+
+    pub fn predict_blocking(&self, p: Point2i, steps: usize) -> bool {
+        self.element_at(p).is_some_and(|e| e.predict_blocking(p, steps))
+    }
+
+    /// The state of every element, in the order they were added.
+    pub fn states(&self) -> Vec<Vec<usize>> {
+        self.elements.iter().map(|e| e.state()).collect()
+    }
+
+    pub fn restore_states(&mut self, states: &[Vec<usize>]) {
+        for (element, state) in self.elements.iter_mut().zip(states) {
+            if !state.is_empty() {
+                element.set_state(state);
+            }
+        }
+    }
+
+    /// Ticks every element, returns the indices of the elements that changed.
+    pub fn tick(&mut self) -> Vec<usize> {
+        (0..self.elements.len()).filter(|i| self.elements[*i].tick()).collect()
+    }
+
+    /// The appearance of the element with index `index`, taken from its first cell.
+    pub fn appearance_of(&self, index: usize) -> Option<Appearance> {
+        let element = self.elements.get(index)?;
+        element.cells().first().map(|cell| element.appearance(*cell))
+    }
+
+    /// Signals `channel` to every element, returns the indices of the elements that changed.
+    pub fn trigger(&mut self, channel: u32) -> Vec<usize> {
+        (0..self.elements.len()).filter(|i| self.elements[*i].on_trigger(channel)).collect()
+    }
+
+    /// Lets every element look at the snakes after they moved and signals the channels it returns.
+    /// Returns the indices of the elements that changed.
+    pub fn after_move(&mut self, occupied: &dyn Fn(Point2i) -> bool) -> Vec<usize> {
+        let channels: Vec<u32> = self.elements.iter_mut().filter_map(|e| e.after_move(occupied)).collect();
+        let mut changed: Vec<usize> = channels.into_iter().flat_map(|channel| self.trigger(channel)).collect();
+        changed.sort_unstable();
+        changed.dedup();
+        changed
+    }
+}
+
+
+pub struct Wall {
+    cells: Vec<Point2i>,
+}
+
+impl Wall {
+    pub fn new(cells: Vec<Point2i>) -> Wall {
+        Wall {
+            cells,
+        }
+    }
+}
+
+impl GameElement for Wall {
+    fn cells(&self) -> &[Point2i] {
+        &self.cells
+    }
+
+    fn on_enter(&self, _cell: Point2i, _dir: Direction) -> HeadProgress {
+        HeadProgress::Collide
+    }
+
+    fn is_blocking(&self, _cell: Point2i) -> bool {
+        true
+    }
+
+    fn appearance(&self, _cell: Point2i) -> Appearance {
+        Appearance::Wall
+    }
+}
+
+
+/// The direction a snake faces when it leaves a teleporter.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ExitDirection {
+    Keep,
+    TurnLeft,
+    TurnRight,
+    Reverse,
+    Fixed(Direction),
+}
+
+impl ExitDirection {
+    fn apply(self, dir: Direction) -> Option<Direction> {
+        match self {
+            ExitDirection::Keep => None,
+            ExitDirection::TurnLeft => Some(dir.turn_left()),
+            ExitDirection::TurnRight => Some(dir.turn_right()),
+            ExitDirection::Reverse => Some(dir.opposite()),
+            ExitDirection::Fixed(fixed) => Some(fixed),
+        }
+    }
+}
+
+/// How a teleporter with several exits picks the next one.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ExitChoice {
+    RoundRobin,
+    Random,
+}
+
+
+/// Sends the snake from its entrance to one of its exits. A teleporter with a single exit
+/// can be two-way, then the exit sends the snake back to the entrance.
+#[derive(Clone, Debug)]
+pub struct Teleporter {
+    pub entrance: Point2i,
+    pub exits: Vec<Point2i>,
+    pub two_way: bool,
+    pub choice: ExitChoice,
+    pub exit_direction: ExitDirection,
+    /// Steps after every use in which the teleporter lets the snake pass without teleporting it.
+    pub cooldown: usize,
+    /// Whether food placed on the entrance is sent to the exit, like the snake.
+    pub carries_food: bool,
+    /// Number of the teleporter in its level, frontends draw all of its cells in the same colour.
+    pub link: usize,
+    cells: Vec<Point2i>,
+    next_exit: usize,
+    cooling: usize,
+    /// State of a xorshift generator for random exits.
+    random: u32,
+}
+
+impl Teleporter {
+    pub fn new(entrance: Point2i, exits: Vec<Point2i>, link: usize) -> Teleporter {
+        let mut cells = vec![entrance];
+        cells.extend(&exits);
+        Teleporter {
+            entrance,
+            two_way: exits.len() == 1,
+            exits,
+            choice: ExitChoice::RoundRobin,
+            exit_direction: ExitDirection::Keep,
+            cooldown: 0,
+            carries_food: false,
+            link,
+            cells,
+            next_exit: 0,
+            cooling: 0,
+            random: 1,
+        }
+    }
+
+    /// A teleporter which sends the snake back and forth between two cells.
+    pub fn two_way(a: Point2i, b: Point2i, link: usize) -> Teleporter {
+        Teleporter::new(a, vec![b], link)
+    }
+
+    /// Seeds the choice of random exits, so every game picks its own.
+    pub fn reseed(&mut self, seed: u64) {
+        // xorshift gets stuck on zero
+        self.random = (seed ^ (seed >> 32)) as u32 ^ (self.link as u32).wrapping_mul(0x9e37_79b9) | 1;
+        if self.choice == ExitChoice::Random {
+            self.pick_exit();
+        }
+    }
+
+    fn pick_exit(&mut self) {
+        self.next_exit = match self.choice {
+            ExitChoice::RoundRobin => (self.next_exit + 1) % self.exits.len(),
+            ExitChoice::Random => {
+                self.random ^= self.random << 13;
+                self.random ^= self.random >> 17;
+                self.random ^= self.random << 5;
+                self.random as usize % self.exits.len()
+            }
+        };
+    }
+
+    /// Where the snake moving onto `cell` ends up after `steps` more ticks, `None` if it just passes.
+    fn destination(&self, cell: Point2i, steps: usize) -> Option<Point2i> {
+        if self.cooling > steps {
+            None
+        } else if cell == self.entrance {
+            Some(self.exits[self.next_exit])
+        } else if self.two_way {
+            Some(self.entrance)
+        } else {
+            None
+        }
+    }
+}
+
+impl GameElement for Teleporter {
+    fn cells(&self) -> &[Point2i] {
+        &self.cells
+    }
+
+    fn on_enter(&self, cell: Point2i, dir: Direction) -> HeadProgress {
+        self.predict_enter(cell, dir, 0)
+    }
+
+    fn is_blocking(&self, _cell: Point2i) -> bool {
+        false
+    }
+
+    fn appearance(&self, cell: Point2i) -> Appearance {
+        Appearance::Teleporter {
+            link: self.link,
+            exit_only: cell != self.entrance && !self.two_way,
+            active: self.cooling == 0,
+        }
+    }
+
+    fn predict_enter(&self, cell: Point2i, dir: Direction, steps: usize) -> HeadProgress {
+        match self.destination(cell, steps) {
+            Some(to) => HeadProgress::Redirect { to, dir: self.exit_direction.apply(dir) },
+            None => HeadProgress::Pass,
+        }
+    }
+
+    fn state(&self) -> Vec<usize> {
+        vec![self.next_exit, self.cooling, self.random as usize]
+    }
+
+    fn set_state(&mut self, state: &[usize]) {
+        if let [next_exit, cooling, random] = *state {
+            self.next_exit = next_exit.min(self.exits.len() - 1);
+            self.cooling = cooling;
+            self.random = random as u32;
+        }
+    }
+
+    fn entered(&mut self, cell: Point2i) {
+        if self.destination(cell, 0).is_some() {
+            // the tick of the next step already counts down
+            if self.cooldown > 0 {
+                self.cooling = self.cooldown + 1;
+            }
+            if cell == self.entrance {
+                self.pick_exit();
+            }
+        }
+    }
+
+    fn tick(&mut self) -> bool {
+        if self.cooling == 0 {
+            return false;
+        }
+        self.cooling -= 1;
+        self.cooling == 0
+    }
+
+    fn carry_food(&self, cell: Point2i) -> Option<Point2i> {
+        self.destination(cell, 0).filter(|_| self.carries_food)
+    }
+}
+
+
+/// What happens to a snake when a door closes on it.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ClosingRule {
+    /// The snake loses every segment from the tail up to the door, or dies if its head is in the door.
+    Cut,
+    Kill,
+    /// The door stays open until no snake covers it anymore.
+    Wait,
+}
+
+impl ClosingRule {
+    pub fn name(self) -> &'static str {
+        match self {
+            ClosingRule::Cut => "cut",
+            ClosingRule::Kill => "kill",
+            ClosingRule::Wait => "wait",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ClosingRule> {
+        match name {
+            "cut" => Some(ClosingRule::Cut),
+            "kill" => Some(ClosingRule::Kill),
+            "wait" => Some(ClosingRule::Wait),
+            _ => None,
+        }
+    }
+}
+
+/// What opens and closes a door.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DoorControl {
+    /// Repeats the phases of `(open, steps)` forever, starting `offset` steps into the first one.
+    Timer { phases: Vec<(bool, usize)>, offset: usize },
+    /// Closed at first, every signal on the door's channel toggles it.
+    Switch,
+    /// Closed until the first signal on the door's channel, then open for good.
+    Key,
+    /// Closed until that many pieces of food were eaten, then open for good.
+    Food(usize),
+}
+
+impl DoorControl {
+    /// A door which toggles between closed and open every `period` steps, starting closed.
+    pub fn periodic(period: usize) -> DoorControl {
+        DoorControl::Timer { phases: vec![(false, period + 1), (true, period + 1)], offset: 0 }
+    }
+
+    /// Whether a timer is open after `ticks` ticks, `None` for other controls.
+    fn timer_open(&self, ticks: usize) -> Option<bool> {
+        let (phases, offset) = match self {
+            DoorControl::Timer { phases, offset } => (phases, offset),
+            _ => return None,
+        };
+
+        let total: usize = phases.iter().map(|phase| phase.1).sum();
+        let mut t = (ticks + offset) % total.max(1);
+        for &(open, steps) in phases {
+            if t < steps {
+                return Some(open);
+            }
+            t -= steps;
+        }
+        Some(false)
+    }
+}
+
+
+pub struct Door {
+    cells: Vec<Point2i>,
+    control: DoorControl,
+    closing: ClosingRule,
+    /// Signals on this channel toggle or open `Switch` and `Key` doors.
+    channel: u32,
+    pub open: bool,
+    /// Whether the control wants the door open, a waiting door may still be held open by a snake.
+    wanted: bool,
+    /// Ticks since the start for timers, pieces of food eaten for `DoorControl::Food`.
+    counter: usize,
+    /// Whether a snake covered one of the cells after the last move.
+    occupied: bool,
+}
+
+impl Door {
+    pub fn new(cells: Vec<Point2i>, control: DoorControl, closing: ClosingRule, channel: u32) -> Door {
+        // a food door waiting for no food at all is open from the start
+        let open = control.timer_open(0).unwrap_or(control == DoorControl::Food(0));
+        Door {
+            cells,
+            control,
+            closing,
+            channel,
+            open,
+            wanted: open,
+            counter: 0,
+            occupied: false,
+        }
+    }
+
+    /// Whether the door will be open after `steps` more ticks. Doors which don't run
+    /// on a timer are expected to end up as their control wants them.
+    pub fn open_after(&self, steps: usize) -> bool {
+        if steps == 0 {
+            return self.open;
+        }
+        self.control.timer_open(self.counter + steps).unwrap_or(self.wanted)
+    }
+
+    /// Whether the door is open now, but closes within `DOOR_WARNING_STEPS`.
+    pub fn is_closing(&self) -> bool {
+        self.open && (1..=DOOR_WARNING_STEPS).any(|steps| !self.open_after(steps))
+    }
+
+    /// Opens or closes the door as wanted unless a snake holds it open, returns whether it changed.
+    fn update(&mut self) -> bool {
+        let held = self.closing == ClosingRule::Wait && self.open && self.occupied;
+        let open = self.wanted || held;
+        std::mem::replace(&mut self.open, open) != open
+    }
+}
+
+
+impl GameElement for Door {
+    fn cells(&self) -> &[Point2i] {
+        &self.cells
+    }
+
+    fn on_enter(&self, _cell: Point2i, _dir: Direction) -> HeadProgress {
+        if self.open {
+            HeadProgress::Pass
+        } else {
+            HeadProgress::Collide
+        }
+    }
+
+    fn is_blocking(&self, _cell: Point2i) -> bool {
+        !self.open
+    }
+
+    fn appearance(&self, _cell: Point2i) -> Appearance {
+        Appearance::Door { open: self.open, closing: self.is_closing() }
+    }
+
+    fn predict_enter(&self, _cell: Point2i, _dir: Direction, steps: usize) -> HeadProgress {
+        if self.open_after(steps) {
+            HeadProgress::Pass
+        } else {
+            HeadProgress::Collide
+        }
+    }
+
+    fn predict_blocking(&self, _cell: Point2i, steps: usize) -> bool {
+        !self.open_after(steps)
+    }
+
+    fn state(&self) -> Vec<usize> {
+        vec![self.open as usize, self.wanted as usize, self.counter, self.occupied as usize]
+    }
+
+    fn set_state(&mut self, state: &[usize]) {
+        if let [open, wanted, counter, occupied] = *state {
+            self.open = open != 0;
+            self.wanted = wanted != 0;
+            self.counter = counter;
+            self.occupied = occupied != 0;
+        }
+    }
+
+    fn closing_rule(&self) -> ClosingRule {
+        self.closing
+    }
+
+    fn next_change(&self) -> Option<usize> {
+        let cycle: usize = match &self.control {
+            DoorControl::Timer { phases, .. } => phases.iter().map(|phase| phase.1).sum(),
+            _ => return None,
+        };
+        (1..=cycle).find(|&steps| self.open_after(steps) != self.open)
+    }
+
+    fn tick(&mut self) -> bool {
+        if let Some(open) = self.control.timer_open(self.counter + 1) {
+            self.counter += 1;
+            self.wanted = open;
+        }
+        self.update()
+    }
+
+    fn entered(&mut self, _cell: Point2i) {
+        // a head entering during the move is covered before `after_move` notices
+        self.occupied = true;
+    }
+
+    fn after_move(&mut self, occupied: &dyn Fn(Point2i) -> bool) -> Option<u32> {
+        self.occupied = self.cells.iter().any(|cell| occupied(*cell));
+        None
+    }
+
+    fn on_trigger(&mut self, channel: u32) -> bool {
+        match self.control {
+            DoorControl::Switch if channel == self.channel => self.wanted = !self.wanted,
+            DoorControl::Key if channel == self.channel => self.wanted = true,
+            DoorControl::Food(count) if channel == FOOD_CHANNEL => {
+                self.counter += 1;
+                self.wanted = self.counter >= count;
+            }
+            _ => (),
+        }
+        self.update()
+    }
+}
+
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum TriggerKind {
+    /// Signals every time a snake head enters it.
+    Switch,
+    /// Signals when the first snake steps on it and again when the last one leaves.
+    Plate,
+    /// Signals once when a snake head picks it up.
+    Key,
+}
+
+impl TriggerKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            TriggerKind::Switch => "switch",
+            TriggerKind::Plate => "plate",
+            TriggerKind::Key => "key",
+        }
+    }
+}
+
+
+/// Switches, pressure plates and keys, which signal a channel to open or close doors.
+pub struct Trigger {
+    kind: TriggerKind,
+    cells: Vec<Point2i>,
+    channel: u32,
+    /// Whether a plate is pressed or a key was taken.
+    active: bool,
+}
+
+impl Trigger {
+    pub fn new(kind: TriggerKind, cells: Vec<Point2i>, channel: u32) -> Trigger {
+        Trigger {
+            kind,
+            cells,
+            channel,
+            active: false,
+        }
+    }
+}
+
+impl GameElement for Trigger {
+    fn cells(&self) -> &[Point2i] {
+        &self.cells
+    }
+
+    fn on_enter(&self, _cell: Point2i, _dir: Direction) -> HeadProgress {
+        match self.kind {
+            TriggerKind::Switch => HeadProgress::Trigger(self.channel),
+            TriggerKind::Key if !self.active => HeadProgress::Trigger(self.channel),
+            _ => HeadProgress::Pass,
+        }
+    }
+
+    fn is_blocking(&self, _cell: Point2i) -> bool {
+        false
+    }
+
+    fn appearance(&self, _cell: Point2i) -> Appearance {
+        match self.kind {
+            TriggerKind::Switch => Appearance::Switch,
+            TriggerKind::Plate => Appearance::Plate { pressed: self.active },
+            TriggerKind::Key => Appearance::Key { taken: self.active },
+        }
+    }
+
+    fn state(&self) -> Vec<usize> {
+        match self.kind {
+            TriggerKind::Switch => Vec::new(),
+            _ => vec![self.active as usize],
+        }
+    }
+
+    fn set_state(&mut self, state: &[usize]) {
+        if let [active] = *state {
+            self.active = active != 0;
+        }
+    }
+
+    fn entered(&mut self, _cell: Point2i) {
+        if self.kind == TriggerKind::Key {
+            self.active = true;
+        }
+    }
+
+    fn after_move(&mut self, occupied: &dyn Fn(Point2i) -> bool) -> Option<u32> {
+        if self.kind != TriggerKind::Plate {
+            return None;
+        }
+        let pressed = self.cells.iter().any(|cell| occupied(*cell));
+        if pressed == self.active {
+            return None;
+        }
+        self.active = pressed;
+        Some(self.channel)
+    }
+}
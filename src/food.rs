@@ -0,0 +1,141 @@
+//! Kinds of food and what they do to the snake that eats them.
+//!
+//! There is always one piece of normal food on the board. Whenever it is eaten, a special
+//! kind may appear in addition, picked by its spawn weight. Special food disappears after
+//! its lifetime unless it is eaten first. Speed-up and slow-down food change the speed of the
+//! game for a while, as an `Effect`.
+
+use crate::engine::Point2i;
+
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum FoodKind {
+    Normal,
+    /// Worth extra points.
+    Bonus,
+    /// Makes the snake shorter.
+    Shrink,
+    /// Costs length and gives no points.
+    Poison,
+    SpeedUp,
+    SlowDown,
+    /// Rare and worth a lot of points.
+    Golden,
+}
+
+impl FoodKind {
+    pub const ALL: [FoodKind; 7] = [
+        FoodKind::Normal,
+        FoodKind::Bonus,
+        FoodKind::Shrink,
+        FoodKind::Poison,
+        FoodKind::SpeedUp,
+        FoodKind::SlowDown,
+        FoodKind::Golden,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FoodKind::Normal => "normal",
+            FoodKind::Bonus => "bonus",
+            FoodKind::Shrink => "shrink",
+            FoodKind::Poison => "poison",
+            FoodKind::SpeedUp => "speed-up",
+            FoodKind::SlowDown => "slow-down",
+            FoodKind::Golden => "golden",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FoodKind> {
+        FoodKind::ALL.iter().copied().find(|kind| kind.name() == name)
+    }
+
+    /// Relative chance to appear when normal food is eaten. The weight of `Normal`
+    /// is the chance that no special food appears.
+    pub fn spawn_weight(self) -> u32 {
+        match self {
+            FoodKind::Normal => 60,
+            FoodKind::Bonus => 12,
+            FoodKind::Shrink => 8,
+            FoodKind::Poison => 6,
+            FoodKind::SpeedUp => 6,
+            FoodKind::SlowDown => 6,
+            FoodKind::Golden => 2,
+        }
+    }
+
+    /// Grid steps the food stays on the board, `None` if it never disappears.
+    pub fn lifetime(self) -> Option<usize> {
+        match self {
+            FoodKind::Normal => None,
+            FoodKind::Bonus => Some(40),
+            FoodKind::Shrink => Some(50),
+            FoodKind::Poison => Some(60),
+            FoodKind::SpeedUp => Some(40),
+            FoodKind::SlowDown => Some(40),
+            FoodKind::Golden => Some(25),
+        }
+    }
+
+    pub fn points(self) -> usize {
+        match self {
+            FoodKind::Normal => 1,
+            FoodKind::Bonus => 5,
+            FoodKind::Shrink => 1,
+            FoodKind::Poison => 0,
+            FoodKind::SpeedUp => 2,
+            FoodKind::SlowDown => 1,
+            FoodKind::Golden => 20,
+        }
+    }
+
+    /// Change of the snake length, a snake never gets shorter than one cell.
+    pub fn growth(self) -> i32 {
+        match self {
+            FoodKind::Normal | FoodKind::Bonus | FoodKind::SpeedUp | FoodKind::SlowDown => 1,
+            FoodKind::Shrink => -3,
+            FoodKind::Poison => -2,
+            FoodKind::Golden => 3,
+        }
+    }
+
+    /// Grid steps the effect of eating the food lasts, `None` for kinds without a lasting effect.
+    pub fn effect_steps(self) -> Option<usize> {
+        match self {
+            FoodKind::SpeedUp | FoodKind::SlowDown => Some(60),
+            _ => None,
+        }
+    }
+
+    /// Factor applied to the step time on top of the speed curve while the effect lasts.
+    pub fn step_time_factor(self) -> f32 {
+        match self {
+            FoodKind::SpeedUp => 0.8,
+            FoodKind::SlowDown => 1.25,
+            _ => 1.0,
+        }
+    }
+
+    /// Whether a sensible player tries to eat this kind.
+    pub fn is_wanted(self) -> bool {
+        self != FoodKind::Poison
+    }
+}
+
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Food {
+    pub kind: FoodKind,
+    pub position: Point2i,
+    /// The grid step in which the food disappears.
+    pub expires_at: Option<usize>,
+}
+
+
+/// Lasting change of the game after eating special food, see `FoodKind::effect_steps`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Effect {
+    pub kind: FoodKind,
+    /// The grid step in which the effect ends.
+    pub ends_at: usize,
+}
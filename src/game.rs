@@ -1,124 +1,327 @@
 use piston_window::*;
-use crate::engine;
+use rust_snake::ai::Autopilot;
+use rust_snake::engine;
+use rust_snake::field::Appearance;
+use rust_snake::food::FoodKind;
+use rust_snake::level::Level;
+use rust_snake::replay::Replay;
+use rust_snake::save::SaveGame;
 
-use engine::GameEvent;
+use engine::{DeathCause, Direction, GameEvent, Input, Point2i};
+
+use crate::sprites::{self, draw_sprite, Sprite};
+use crate::theme::Skin;
+use crate::hud::{self, draw_hud, HudInfo};
+use crate::minimap::draw_minimap;
+use crate::view::{BoardView, Camera};
+
+
+/// Up, down, left and right key of every player.
+const PLAYER_KEYS: [[Key; 4]; 4] = [
+    [Key::Up, Key::Down, Key::Left, Key::Right],
+    [Key::W, Key::S, Key::A, Key::D],
+    [Key::I, Key::K, Key::J, Key::L],
+    [Key::NumPad8, Key::NumPad5, Key::NumPad4, Key::NumPad6],
+];
+
+/// Special food starts blinking this many grid steps before it disappears.
+const FOOD_BLINK_STEPS: usize = 6;
+
+/// Maps a key to the player it belongs to and the input it stands for.
+pub fn key_to_input(key: Key) -> Option<(usize, Input)> {
+    let directions = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+
+    PLAYER_KEYS.iter().enumerate().find_map(|(player, keys)| {
+        keys.iter().position(|k| *k == key).map(|i| (player, Input::Turn(directions[i])))
+    })
+}
 
 
 pub struct MainGame {
-    rect_size: f64,
-    field_size: f64,
     game: engine::Game,
+    autopilots: Vec<Autopilot>,
+    /// Why the first snake died, for the end screen of single player games.
+    death_cause: Option<DeathCause>,
+    camera: Camera,
+    /// Whether boards larger than the window get an overview in the corner.
+    show_minimap: bool,
+    /// Highest score of the session before this game, shown in the HUD.
+    high_score: usize,
+    /// Shown in the HUD, e.g. the state of a replay.
+    status: Option<String>,
 }
 
 
 impl MainGame {
-    pub fn new(rect_size: f64,
-               field_size: f64) -> MainGame {
+    pub fn new(level: &Level, seed: Option<u64>) -> MainGame {
+        let mut game = match seed {
+            Some(seed) => engine::Game::with_seed(level, seed),
+            None => engine::Game::from_level(level),
+        };
+        println!("Starting game with seed {}", game.seed());
+        game.start_recording();
+
+        MainGame::from_game(game)
+    }
+
+    pub fn from_game(game: engine::Game) -> MainGame {
+        let camera = Camera::new(game.width(), game.height(), camera_target(&game));
         MainGame {
-            rect_size,
-            field_size,
-            game: engine::Game::new(field_size as i32, field_size as i32),
+            game,
+            autopilots: Vec::new(),
+            death_cause: None,
+            camera,
+            show_minimap: true,
+            high_score: 0,
+            status: None,
         }
     }
 
-    pub fn current_score(&self) -> u32 {
-        self.game.current_length() as u32
+    /// A new game on the same level with the same rules and computer opponents.
+    pub fn restart(&self, seed: Option<u64>) -> MainGame {
+        let mut restarted = MainGame::new(&self.game.level(), seed);
+        for autopilot in &self.autopilots {
+            restarted.add_autopilot(Autopilot::new(autopilot.player(), autopilot.strategy()));
+        }
+        restarted.set_high_score(self.high_score.max(self.best_score()));
+        restarted
+    }
+
+    pub fn set_high_score(&mut self, high_score: usize) {
+        self.high_score = high_score;
+    }
+
+    /// The highest score any snake reached in this game.
+    pub fn best_score(&self) -> usize {
+        self.game.snakes.iter().map(|s| s.score()).max().unwrap_or(0)
+    }
+
+    pub fn set_status(&mut self, status: Option<String>) {
+        self.status = status;
+    }
+
+    /// Hands a snake over to the computer, its player keys are ignored from now on.
+    pub fn add_autopilot(&mut self, autopilot: Autopilot) {
+        self.autopilots.push(autopilot);
+    }
+
+    /// Everything needed to continue the game later, including which snakes the computer steers.
+    pub fn save_state(&self) -> SaveGame {
+        let mut save = self.game.save_state();
+        save.autopilots = self.autopilots.iter().map(|a| (a.player(), a.strategy())).collect();
+        save
+    }
+
+    pub fn game(&self) -> &engine::Game {
+        &self.game
+    }
+
+    pub fn game_mut(&mut self) -> &mut engine::Game {
+        &mut self.game
+    }
+
+    pub fn take_recording(&mut self) -> Option<Replay> {
+        self.game.take_recording()
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.game.is_over()
+    }
+
+    pub fn is_won(&self) -> bool {
+        self.game.is_won()
+    }
+
+    pub fn pause(&mut self) {
+        self.game.pause();
+    }
+
+    pub fn resume(&mut self) {
+        self.game.resume();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.game.is_paused()
     }
 
-    pub fn run(&mut self, window: &mut PistonWindow, glyphs: &mut Glyphs, e: Event) -> GameEvent {
-        let mut game_event = GameEvent::None;
+    /// Text for the end screen: the score, or the winner and all scores in multiplayer games.
+    pub fn result_text(&self) -> String {
+        let scores: Vec<String> = self.game.snakes.iter().map(|s| s.score().to_string()).collect();
 
-        if let Some(button) = e.press_args() {
-            match button {
-                Button::Keyboard(key) => self.game.handle_key(key),
-                _ => (),
+        if self.game.snakes.len() == 1 {
+            if self.game.is_won() {
+                let reason = if self.game.is_time_up() { "You made it until the end" } else { "The board is full" };
+                return format!("You win!\n{}\nScore: {}", reason, scores[0]);
             }
+            let cause = match self.death_cause {
+                Some(DeathCause::Border) => "You hit the border",
+                Some(DeathCause::Obstacle) => "You crashed",
+                Some(DeathCause::OwnBody) => "You bit yourself",
+                Some(DeathCause::Door) => "A door closed on you",
+                _ => " ",
+            };
+            return format!("You lost!\n{}\nScore: {}", cause, scores[0]);
         }
 
-        if let Some(res) = e.update(|arg| {
-            self.game.make_step(arg.dt)
-        }) {
-            if let GameEvent::Collision = res {
-                return GameEvent::Collision;
-            }
+        let result = match self.game.winner() {
+            Some(winner) => format!("Player {} wins!", winner + 1),
+            None => "Draw!".to_string(),
+        };
+        format!("{}\n \nScores: {}", result, scores.join(" / "))
+    }
 
-            game_event = res;
+    pub fn run(&mut self, window: &mut PistonWindow, skin: &mut Skin, e: Event) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+
+        if let Some(Button::Keyboard(Key::M)) = e.press_args() {
+            self.show_minimap = !self.show_minimap;
         }
 
-        window.draw_2d(&e, |c, g, device| {
-            clear([0.95, 0.95, 0.95, 1.0], g);
+        if let Some(Button::Keyboard(key)) = e.press_args() {
+            if let Some((player, input)) = key_to_input(key) {
+                if !self.autopilots.iter().any(|a| a.player() == player) {
+                    self.game.handle_input(player, input);
+                }
+            }
+        }
 
-            for door in &self.game.doors {
-                for cell in &door.cells {
-                    let color = if door.open { [0.9, 0.9, 0.9, 1.0] } else { [0.4, 0.4, 0.4, 1.0] };
-                    rectangle(color,
-                              [cell.x as f64 * self.rect_size, cell.y as f64 * self.rect_size, self.rect_size, self.rect_size], // rectangle
-                              c.transform, g);
+        if let Some(res) = e.update(|arg| {
+            if !self.game.is_paused() {
+                for autopilot in &mut self.autopilots {
+                    autopilot.update(&mut self.game);
                 }
             }
+            let events = self.game.make_step(arg.dt);
+            self.update_camera(arg.dt);
+            events
+        }) {
+            events = res;
+        }
 
-            let snake_slices = self.game.snake_body.as_slices();
+        for event in &events {
+            if let GameEvent::Death { player: 0, cause } = event {
+                self.death_cause = Some(*cause);
+            }
+        }
 
-            if snake_slices.0.len() > 0 {
-                // do not render very first element
-                for p in snake_slices.0[1..].iter() {
-                    rectangle([1.0, 0.2, 0.2, 1.0], // red
-                              [p.0.x as f64 * self.rect_size, p.0.y as f64 * self.rect_size, self.rect_size, self.rect_size], // rectangle
-                              c.transform, g);
-                }
-                
-                for p in snake_slices.1.iter() {
-                    rectangle([1.0, 0.2, 0.2, 1.0], // red
-                              [p.0.x as f64 * self.rect_size, p.0.y as f64 * self.rect_size, self.rect_size, self.rect_size], // rectangle
-                              c.transform, g);
+        self.render(window, skin, &e);
+
+        events
+    }
+
+    /// Lets the camera follow the snakes for `dt` seconds.
+    pub fn update_camera(&mut self, dt: f64) {
+        self.camera.follow(camera_target(&self.game), dt);
+    }
+
+    pub fn render(&mut self, window: &mut PistonWindow, skin: &mut Skin, e: &Event) {
+        let theme = &skin.theme;
+        let sprites = skin.sprites.as_ref();
+        let glyphs = &mut skin.glyphs;
+
+        let camera = &mut self.camera;
+        let show_minimap = self.show_minimap;
+        let game = &self.game;
+        let info = HudInfo { high_score: self.high_score, status: self.status.as_deref() };
+
+        window.draw_2d(e, |c, g, device| {
+            clear(theme.background, g);
+
+            let (hud_area, board_area) = hud::layout(theme, c.get_view_size());
+            let view = BoardView::new(board_area, game.width(), game.height(), camera);
+            let rect = |x: f64, y: f64| view.cell(x, y);
+            let cell = |p: Point2i| view.cell(p.x as f64, p.y as f64);
+            let mut draw = |sprite, color, rect, dir| draw_sprite(sprites, sprite, color, rect, dir, &c, g);
+
+            // doors, switches and plates are drawn below the snake, all other elements on top of it
+            for (p, appearance) in game.field.cells() {
+                match appearance {
+                    Appearance::Door { open, closing } => {
+                        // doors about to close flash in orange
+                        let color = match (open, closing && game.step_count() % 2 == 1) {
+                            (true, true) => theme.door_closing,
+                            (true, false) => theme.door_open,
+                            (false, _) => theme.door,
+                        };
+                        let sprite = if open { Sprite::OpenDoor } else { Sprite::Door };
+                        draw(sprite, color, cell(p), Direction::Right);
+                    }
+                    Appearance::Switch => draw(Sprite::Switch, theme.switch, cell(p), Direction::Right),
+                    Appearance::Plate { pressed } => {
+                        let color = if pressed { theme.plate_pressed } else { theme.plate };
+                        draw(Sprite::Plate, color, cell(p), Direction::Right);
+                    }
+                    _ => (),
                 }
             }
 
-            self.game.get_interpolated_snake_tail().map(|snake_tail| {
-                rectangle([1.0, 0.2, 0.2, 1.0], // red
-                          [snake_tail.x as f64 * self.rect_size, snake_tail.y as f64 * self.rect_size, self.rect_size, self.rect_size], // rectangle
-                          c.transform, g);
-            });
+            for (i, snake) in game.snakes.iter().enumerate() {
+                let (body_color, head_color) = if snake.is_alive() {
+                    theme.player_colors(i)
+                } else {
+                    (theme.dead, theme.dead)
+                };
+                let dir_of = |v: Point2i| Direction::from_vector(v).unwrap_or_else(|| snake.dir());
 
+                // do not render very first element, the interpolated tail replaces it
+                for (before, segment) in snake.body.iter().zip(snake.body.iter().skip(1)) {
+                    let (sprite, dir) = sprites::segment(dir_of(before.1), dir_of(segment.1));
+                    draw(sprite, body_color, cell(segment.0), dir);
+                }
 
+                if let (Some(snake_tail), Some(tail)) = (game.get_interpolated_snake_tail(i), snake.body.front()) {
+                    draw(Sprite::Tail, body_color, rect(snake_tail.x as f64, snake_tail.y as f64), dir_of(tail.1));
+                }
 
-            let snake_head = self.game.get_interpolated_snake_head();
+                let snake_head = game.get_interpolated_snake_head(i);
+                draw(Sprite::Head, head_color, rect(snake_head.x as f64, snake_head.y as f64), snake.dir());
+            }
 
-            rectangle([0.8, 0.0, 0.0, 1.0], // red
-                      [snake_head.x as f64 * self.rect_size, snake_head.y as f64 * self.rect_size, self.rect_size, self.rect_size], // rectangle
-                      c.transform, g);
+            for food in &game.food {
+                let steps_left = food.expires_at.map_or(usize::MAX, |at| at.saturating_sub(game.step_count()));
+                if steps_left <= FOOD_BLINK_STEPS && steps_left % 2 == 0 {
+                    continue;
+                }
 
-            rectangle([0.1, 0.8, 0.1, 1.0], // green
-                      [self.game.food_location.x as f64 * self.rect_size, self.game.food_location.y as f64 * self.rect_size, self.rect_size, self.rect_size], // rectangle
-                      c.transform, g);
+                // special food is round to tell it apart from the snakes
+                let sprite = if food.kind == FoodKind::Normal { Sprite::Logo } else { Sprite::Food };
+                draw(sprite, theme.food_color(food.kind), cell(food.position), Direction::Right);
+            }
 
-            for y in 0..self.game.game_field.num_rows() {
-                for x in 0..self.game.game_field.num_columns() {
-                    if self.game.game_field[(y, x)] > 0 {
-                        rectangle([0.3, 0.3, 0.3, 1.0],
-                                  [x as f64 * self.rect_size, y as f64 * self.rect_size, self.rect_size, self.rect_size], // rectangle
-                                  c.transform, g);
+            for (p, appearance) in game.field.cells() {
+                match appearance {
+                    Appearance::Wall => draw(Sprite::Wall, theme.wall, cell(p), Direction::Right),
+                    Appearance::Teleporter { link, exit_only, active } => {
+                        let mut color = theme.teleporter_color(link);
+                        if !active {
+                            color[3] = 0.35;
+                        }
+                        // cells which only receive the snake are drawn as a frame
+                        let sprite = if exit_only { Sprite::TeleporterExit } else { Sprite::Teleporter };
+                        draw(sprite, color, cell(p), Direction::Right);
                     }
+                    Appearance::Key { taken: false } => draw(Sprite::Key, theme.key, cell(p), Direction::Right),
+                    _ => (),
                 }
             }
 
-            for t in &self.game.teleporters {
-                rectangle([0.1, 0.1, 0.9, 1.0],
-                          [t.start.x as f64 * self.rect_size, t.start.y as f64 * self.rect_size, self.rect_size, self.rect_size], // rectangle
-                          c.transform, g);
-                rectangle([0.1, 0.5, 0.9, 1.0],
-                          [t.end.x as f64 * self.rect_size, t.end.y as f64 * self.rect_size, self.rect_size, self.rect_size], // rectangle
-                          c.transform, g);
+            view.draw_bars(theme.letterbox, &c, g);
+            if show_minimap && view.scrolls() {
+                draw_minimap(game, theme, &view, &c, g);
             }
 
-            text([0.0, 0.0, 0.0, 1.0], 16, &self.game.current_length().to_string(), glyphs,
-                 c.transform.trans(2.0, self.rect_size * self.field_size - 2.0), g).unwrap();
+            draw_hud(game, theme, &info, hud_area, glyphs, &c, g);
 
             // Update glyphs before rendering.
             glyphs.factory.encoder.flush(device);
         });
-
-
-        return game_event;
     }
-}
\ No newline at end of file
+}
+
+/// The point the camera looks at: the middle of the first snake's head, which is the human player
+/// in games against the computer. Once that snake died the camera stays where it died rather than
+/// jumping to another snake.
+fn camera_target(game: &engine::Game) -> [f64; 2] {
+    let head = game.get_interpolated_snake_head(0);
+    [head.x as f64 + 0.5, head.y as f64 + 0.5]
+}
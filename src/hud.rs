@@ -0,0 +1,192 @@
+//! Panel with scores, timers, power-ups and the state of the game, placed beside the board so it never
+//! covers any cell. Its colours, font size and side of the window come from the theme.
+
+use piston_window::character::CharacterCache;
+use piston_window::*;
+use rust_snake::engine::Game;
+use rust_snake::field::Appearance;
+use rust_snake::food::FoodKind;
+
+use crate::theme::{Color, HudPosition, Theme};
+
+
+/// The panel always has room for this many rows so the board does not move while playing.
+const ROWS: usize = 3;
+/// Space around the rows in pixels.
+const PADDING: f64 = 4.0;
+/// Width of a panel on the left or right in multiples of the font size.
+const SIDE_WIDTH: f64 = 12.0;
+
+/// What the HUD shows besides the game itself.
+pub struct HudInfo<'a> {
+    /// Highest score reached in this session.
+    pub high_score: usize,
+    /// Information about the current mode, like the speed of a replay.
+    pub status: Option<&'a str>,
+}
+
+/// A value with a label in front of it.
+struct Item {
+    label: String,
+    label_color: Color,
+    value: String,
+}
+
+impl Item {
+    fn new(label: &str, theme: &Theme, value: String) -> Item {
+        Item { label: label.to_string(), label_color: theme.hud_label, value }
+    }
+}
+
+fn row_height(theme: &Theme) -> f64 {
+    (theme.hud_font_size as f64 * 1.4).ceil()
+}
+
+fn is_side(theme: &Theme) -> bool {
+    matches!(theme.hud_position, HudPosition::Left | HudPosition::Right)
+}
+
+/// Room the HUD takes from the window in pixels, as `[width, height]`. Panels on the top
+/// or bottom span the whole width, panels on the left or right the whole height.
+pub fn size(theme: &Theme) -> [f64; 2] {
+    if is_side(theme) {
+        [(theme.hud_font_size as f64 * SIDE_WIDTH).ceil() + 2.0 * PADDING, 0.0]
+    } else {
+        [0.0, ROWS as f64 * row_height(theme) + 2.0 * PADDING]
+    }
+}
+
+/// Splits a window of size `window` into the HUD and the area left for the board,
+/// both as `[x, y, width, height]`.
+pub fn layout(theme: &Theme, window: [f64; 2]) -> ([f64; 4], [f64; 4]) {
+    let [width, height] = size(theme);
+    let (width, height) = (width.min(window[0]), height.min(window[1]));
+    let (rest_x, rest_y) = (window[0] - width, window[1] - height);
+    match theme.hud_position {
+        HudPosition::Top => ([0.0, 0.0, window[0], height], [0.0, height, window[0], rest_y]),
+        HudPosition::Bottom => ([0.0, rest_y, window[0], height], [0.0, 0.0, window[0], rest_y]),
+        HudPosition::Left => ([0.0, 0.0, width, window[1]], [width, 0.0, rest_x, window[1]]),
+        HudPosition::Right => ([rest_x, 0.0, width, window[1]], [0.0, 0.0, rest_x, window[1]]),
+    }
+}
+
+fn format_time(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Scores and lengths, one entry per snake in multiplayer games.
+fn player_row(game: &Game, theme: &Theme) -> Vec<Item> {
+    if let [snake] = &game.snakes[..] {
+        return vec![
+            Item::new("score", theme, snake.score().to_string()),
+            Item::new("length", theme, snake.length().to_string()),
+        ];
+    }
+
+    game.snakes.iter().enumerate().map(|(i, snake)| {
+        let label_color = if snake.is_alive() { theme.player_colors(i).1 } else { theme.dead };
+        let value = format!("{} ({} long)", snake.score(), snake.length());
+        Item { label: format!("P{}", i + 1), label_color, value }
+    }).collect()
+}
+
+/// Time, the time left in timed games, speed, high score and the rules in effect.
+fn game_row(game: &Game, theme: &Theme, info: &HudInfo) -> Vec<Item> {
+    let best = game.snakes.iter().map(|s| s.score()).max().unwrap_or(0).max(info.high_score);
+    let mut items = vec![
+        Item::new("time", theme, format_time(game.play_time())),
+        Item::new("speed", theme, format!("{:.1} cells/s", 1.0 / game.step_time())),
+        Item::new("best", theme, best.to_string()),
+        Item::new("border", theme, game.border().name().to_string()),
+    ];
+    if let Some(left) = game.time_left() {
+        items.insert(1, Item::new("left", theme, format_time(left.ceil())));
+    }
+    if let Some(status) = info.status {
+        items.push(Item::new("mode", theme, status.to_string()));
+    }
+    items
+}
+
+/// Grid steps until timed doors open or close, until special food disappears and until the
+/// effects of eaten food wear off.
+fn timer_row(game: &Game, theme: &Theme) -> Vec<Item> {
+    let mut items = Vec::new();
+
+    let doors: Vec<String> = game.field.elements().filter_map(|element| {
+        let steps = element.next_change()?;
+        let open = matches!(element.appearance(*element.cells().first()?), Appearance::Door { open: true, .. });
+        Some(format!("{} in {}", if open { "closes" } else { "opens" }, steps))
+    }).collect();
+    if !doors.is_empty() {
+        items.push(Item::new("doors", theme, doors.join(", ")));
+    }
+
+    for food in game.food.iter().filter(|food| food.kind != FoodKind::Normal) {
+        if let Some(expires_at) = food.expires_at {
+            let steps = expires_at.saturating_sub(game.step_count());
+            let label_color = theme.food_color(food.kind);
+            items.push(Item { label: food.kind.name().to_string(), label_color, value: format!("{} left", steps) });
+        }
+    }
+
+    for effect in game.effects() {
+        let steps = effect.ends_at.saturating_sub(game.step_count());
+        let label_color = theme.food_color(effect.kind);
+        items.push(Item { label: effect.kind.name().to_string(), label_color, value: format!("on for {}", steps) });
+    }
+    items
+}
+
+/// Draws the items side by side, in a smaller font if they don't fit into `width`.
+/// `start` is the left end of the baseline.
+fn draw_row(items: &[Item], theme: &Theme, start: [f64; 2], width: f64, glyphs: &mut Glyphs, c: &Context, g: &mut G2d) {
+    let mut size = theme.hud_font_size;
+    let text_width: f64 = items.iter()
+        .map(|item| glyphs.width(size, &item.label).unwrap() + glyphs.width(size, &item.value).unwrap())
+        .sum();
+    // the gaps after labels and values are 0.4 and 1.1 times the font size
+    let full_width = text_width + items.len() as f64 * size as f64 * 1.5;
+    if full_width > width {
+        size = ((size as f64 * width / full_width) as u32).max(1);
+    }
+
+    let [mut x, baseline] = start;
+    for item in items {
+        text(item.label_color, size, &item.label, glyphs, c.transform.trans(x, baseline), g).unwrap();
+        x += glyphs.width(size, &item.label).unwrap() + size as f64 * 0.4;
+        text(theme.hud_text, size, &item.value, glyphs, c.transform.trans(x, baseline), g).unwrap();
+        x += glyphs.width(size, &item.value).unwrap() + size as f64 * 1.1;
+    }
+}
+
+/// Draws the HUD into `area` as returned by `layout`. Panels on the left or right show one
+/// item per line with a gap between the rows, items that don't fit into the height are left out.
+pub fn draw_hud(game: &Game, theme: &Theme, info: &HudInfo, area: [f64; 4],
+                glyphs: &mut Glyphs, c: &Context, g: &mut G2d) {
+    rectangle(theme.hud, area, c.transform, g);
+
+    let rows = [player_row(game, theme), game_row(game, theme, info), timer_row(game, theme)];
+    let height = row_height(theme);
+    let (left, width) = (area[0] + PADDING, area[2] - 2.0 * PADDING);
+    if !is_side(theme) {
+        for (i, row) in rows.iter().enumerate() {
+            let baseline = area[1] + PADDING + (i as f64 + 0.8) * height;
+            draw_row(row, theme, [left, baseline], width, glyphs, c, g);
+        }
+        return;
+    }
+
+    let mut baseline = area[1] + PADDING + 0.8 * height;
+    for row in rows.iter().filter(|row| !row.is_empty()) {
+        for item in row {
+            if baseline > area[1] + area[3] - PADDING {
+                return;
+            }
+            draw_row(std::slice::from_ref(item), theme, [left, baseline], width, glyphs, c, g);
+            baseline += height;
+        }
+        baseline += height / 2.0;
+    }
+}
@@ -0,0 +1,966 @@
+//! Plain-text level files.
+//!
+//! A level file consists of a header with one `key: value` entry per line,
+//! a `---` separator line and an ASCII grid describing the board:
+//!
+//! ```text
+//! # comments and empty lines are allowed in the header
+//! name: Classic
+//! snake: down 5
+//! border: solid
+//! door: A 5
+//! teleport: a b
+//! ---
+//! a.........#.........
+//! .*........#.........
+//! ..........A.........
+//! .....@....#........b
+//! ```
+//!
+//! Grid cells:
+//!
+//! * `.` empty cell
+//! * `#` wall
+//! * `@` or `1` start position of the (first) snake head, `2`-`9` start positions of further snakes
+//! * `*` initial food, optional (placed randomly if missing)
+//! * `A`-`Z` door cells, all cells with the same letter form one door
+//! * `a`-`z` teleporter endpoints, every letter may be used only once
+//! * any other punctuation character declared as a switch, pressure plate or key in the header
+//!
+//! Header entries:
+//!
+//! * `name: <text>`
+//! * `snake: <up|down|left|right> <length>` initial direction and length of the snakes
+//! * `snake <number>: <up|down|left|right> <length>` overrides `snake` for a single snake
+//! * `border: <solid|wrap|bounce>` what happens at the edge of the board, `solid` if missing
+//! * `tail: <follow|block>` whether a head may move into the cell a tail leaves in the same step, `follow` if missing
+//! * `speed: <easy|normal|hard|insane>` speed preset, `normal` if missing
+//! * `speed: <start> <per food> <per minute> <min>` custom speed curve: seconds per cell at the start,
+//!   factors applied for every food eaten and every minute played, and the shortest step time
+//! * `time limit: <seconds>` the game ends after that many seconds, surviving snakes win by score
+//! * `food zone: <x> <y> <width> <height>` food only appears inside the food zones, anywhere if there are none
+//! * `no food zone: <x> <y> <width> <height>` food never appears inside this rectangle
+//! * `door: <letter> <period>` door which toggles between closed and open every `period` steps
+//! * `door: <letter> timer <closed> <open> [phase <steps>]` door which is closed for `closed` steps,
+//!   then open for `open` steps, starting `phase` steps into the cycle
+//! * `door: <letter> pattern <c|o><steps> ... [phase <steps>]` door which runs through a repeating
+//!   pattern of closed and open phases, e.g. `pattern c4 o1 c1 o1`
+//! * `door: <letter> switch` door which starts closed and is toggled by its switches and pressure plates
+//! * `door: <letter> key` door which stays closed until one of its keys is picked up
+//! * `door: <letter> food <count>` door which opens for good once `count` pieces of food were eaten
+//! * every `door` entry may end in `closing <cut|kill|wait>`, what happens to a snake when the door closes
+//!   on it: it loses everything from the tail up to the door, dies, or the door waits until it left (default)
+//! * `switch: <symbol> <door letter>` cells marked with `symbol` signal the door whenever a head enters them
+//! * `plate: <symbol> <door letter>` pressure plate, signals the door when a snake steps on it and again
+//!   when the last snake leaves it, which holds a `switch` door in the other state while it is pressed
+//! * `key: <symbol> <door letter>` key which signals the door once when a head picks it up
+//! * `teleport: <letter> <letter> [<letter> ...] [<option> ...]` teleporter from the first endpoint to the
+//!   others. With a single exit it is two-way unless `oneway` is given, with several exits it takes turns
+//!   between them unless `random` is given. Further options:
+//!   * `exit <up|down|left|right>` the snake always leaves in this direction
+//!   * `turn <left|right|back>` the snake leaves turned by a quarter or half turn
+//!   * `cooldown <steps>` after every use the teleporter lets the snake pass for that many steps
+//!   * `food` food placed on the entrance is sent to the exit
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::engine::{BorderRule, Direction, Point2i, SpeedCurve, TailRule};
+use crate::field::{ClosingRule, DoorControl, ExitChoice, ExitDirection, Teleporter, TriggerKind};
+use crate::parse::{parse_error, tokens, ParseError};
+
+
+#[derive(Clone)]
+pub struct DoorSpec {
+    pub cells: HashSet<Point2i>,
+    pub control: DoorControl,
+    pub closing: ClosingRule,
+}
+
+/// Switches, pressure plates and keys of one symbol.
+#[derive(Clone)]
+pub struct TriggerSpec {
+    pub kind: TriggerKind,
+    pub cells: Vec<Point2i>,
+    /// Index of the signalled door in `Level::doors`.
+    pub door: usize,
+}
+
+/// A rectangle of cells.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Zone {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Zone {
+    pub fn contains(&self, p: Point2i) -> bool {
+        (self.x..self.x + self.width).contains(&p.x) && (self.y..self.y + self.height).contains(&p.y)
+    }
+}
+
+#[derive(Clone)]
+pub struct SnakeStart {
+    pub start: Point2i,
+    pub dir: Direction,
+    pub length: usize,
+}
+
+#[derive(Clone)]
+pub struct Level {
+    pub name: String,
+    pub width: i32,
+    pub height: i32,
+    pub walls: Vec<Point2i>,
+    pub doors: Vec<DoorSpec>,
+    pub triggers: Vec<TriggerSpec>,
+    pub teleporters: Vec<Teleporter>,
+    pub snakes: Vec<SnakeStart>,
+    pub food: Option<Point2i>,
+    pub food_zones: Vec<Zone>,
+    pub no_food_zones: Vec<Zone>,
+    pub border: BorderRule,
+    pub tail: TailRule,
+    pub speed: SpeedCurve,
+    /// Seconds a game lasts, `None` if it goes on until the snakes die.
+    pub time_limit: Option<usize>,
+}
+
+fn parse_letter(line: usize, (column, token): (usize, &str), upper: bool) -> Result<char, ParseError> {
+    let mut chars = token.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) if upper && ch.is_ascii_uppercase() => Ok(ch),
+        (Some(ch), None) if !upper && ch.is_ascii_lowercase() => Ok(ch),
+        _ => parse_error(line, column, format!("expected a single {} letter, found '{}'",
+                                               if upper { "uppercase" } else { "lowercase" }, token)),
+    }
+}
+
+/// Punctuation characters with a fixed meaning in the grid, all others can be declared as triggers.
+const RESERVED_SYMBOLS: &str = ".#@*";
+
+/// Symbols given to triggers when writing a level, the most readable ones first.
+const TRIGGER_SYMBOLS: &str = "!$%&+=?^~<>/|;:,-_'\"`()[]{}\\";
+
+fn parse_symbol(line: usize, (column, token): (usize, &str)) -> Result<char, ParseError> {
+    let mut chars = token.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) if ch.is_ascii_punctuation() && !RESERVED_SYMBOLS.contains(ch) => Ok(ch),
+        _ => parse_error(line, column, format!("expected a punctuation character other than '{}', found '{}'",
+                                               RESERVED_SYMBOLS, token)),
+    }
+}
+
+/// Longest phase, period or phase offset of a door in grid steps.
+const MAX_DOOR_STEPS: usize = 1_000_000;
+
+/// Parses what follows the letter of a `door` entry, `column` is where the letter starts.
+fn parse_door_control(line: usize, column: usize, values: &[(usize, &str)]) -> Result<DoorControl, ParseError> {
+    // limited so that the timers can add them up without overflowing
+    let bounded = |value: (usize, &str)| match parse_number(line, value)? {
+        n if n > MAX_DOOR_STEPS => parse_error(line, value.0, format!("at most {} steps are allowed, found {}", MAX_DOOR_STEPS, n)),
+        n => Ok(n),
+    };
+    let steps = |value: (usize, &str)| match bounded(value)? {
+        0 => parse_error(line, value.0, "a phase lasts at least one step".to_string()),
+        n => Ok(n),
+    };
+
+    let (values, offset) = match values {
+        [rest @ .., (_, "phase"), phase] => (rest, Some(bounded(*phase)?)),
+        _ => (values, None),
+    };
+
+    let control = match values {
+        [(_, "timer"), closed, open] => {
+            DoorControl::Timer { phases: vec![(false, steps(*closed)?), (true, steps(*open)?)], offset: 0 }
+        }
+        [(_, "pattern"), pattern @ ..] if !pattern.is_empty() => {
+            let mut phases = Vec::new();
+            for &(column, token) in pattern {
+                let open = match token.chars().next() {
+                    Some('c') => false,
+                    Some('o') => true,
+                    _ => return parse_error(line, column, format!("expected 'c<steps>' or 'o<steps>', found '{}'", token)),
+                };
+                phases.push((open, steps((column + 1, &token[1..]))?));
+            }
+            DoorControl::Timer { phases, offset: 0 }
+        }
+        [(_, "switch")] => DoorControl::Switch,
+        [(_, "key")] => DoorControl::Key,
+        [(_, "food"), count] => DoorControl::Food(parse_number(line, *count)?),
+        [period] if period.1.starts_with(|ch: char| ch.is_ascii_digit()) => DoorControl::periodic(bounded(*period)?),
+        _ => return parse_error(line, values.first().map_or(column, |v| v.0),
+                                "expected '<period>', 'timer <closed> <open>', 'pattern <c|o><steps> ...', \
+                                 'switch', 'key' or 'food <count>'".to_string()),
+    };
+
+    match (control, offset) {
+        (DoorControl::Timer { phases, .. }, Some(offset)) => Ok(DoorControl::Timer { phases, offset }),
+        (_, Some(_)) => parse_error(line, values[0].0, "only doors on a timer have a phase".to_string()),
+        (control, None) => Ok(control),
+    }
+}
+
+/// Options following the endpoints of a `teleport` entry.
+struct TeleportOptions {
+    one_way: bool,
+    choice: ExitChoice,
+    exit_direction: ExitDirection,
+    cooldown: usize,
+    carries_food: bool,
+}
+
+fn parse_teleport_options(line: usize, values: &[(usize, &str)]) -> Result<TeleportOptions, ParseError> {
+    let mut options = TeleportOptions {
+        one_way: false,
+        choice: ExitChoice::RoundRobin,
+        exit_direction: ExitDirection::Keep,
+        cooldown: 0,
+        carries_food: false,
+    };
+
+    let mut values = values.iter().copied();
+    while let Some((column, option)) = values.next() {
+        match option {
+            "oneway" => options.one_way = true,
+            "random" => options.choice = ExitChoice::Random,
+            "food" => options.carries_food = true,
+            "exit" | "turn" | "cooldown" => {
+                let value = match values.next() {
+                    Some(value) => value,
+                    None => return parse_error(line, column, format!("'{}' expects a value", option)),
+                };
+                match option {
+                    "exit" => options.exit_direction = match Direction::from_name(value.1) {
+                        Some(dir) => ExitDirection::Fixed(dir),
+                        None => return parse_error(line, value.0, format!("unknown direction '{}'", value.1)),
+                    },
+                    "turn" => options.exit_direction = match value.1 {
+                        "left" => ExitDirection::TurnLeft,
+                        "right" => ExitDirection::TurnRight,
+                        "back" => ExitDirection::Reverse,
+                        _ => return parse_error(line, value.0, format!("expected 'left', 'right' or 'back', found '{}'", value.1)),
+                    },
+                    _ => options.cooldown = parse_number(line, value)?,
+                }
+            }
+            _ => return parse_error(line, column, format!("unknown teleporter option '{}'", option)),
+        }
+    }
+
+    Ok(options)
+}
+
+pub(crate) fn parse_number(line: usize, (column, token): (usize, &str)) -> Result<usize, ParseError> {
+    match token.parse() {
+        Ok(n) => Ok(n),
+        Err(_) => parse_error(line, column, format!("expected a number, found '{}'", token)),
+    }
+}
+
+pub(crate) fn expect_tokens(line: usize, column: usize, key: &str, values: &[(usize, &str)], count: usize)
+                            -> Result<(), ParseError> {
+    if values.len() != count {
+        let column = values.get(count).map_or(column, |v| v.0);
+        return parse_error(line, column, format!("'{}' expects {} values, found {}", key, count, values.len()));
+    }
+    Ok(())
+}
+
+impl Level {
+    /// The hardcoded layout the game always had: a wall in the middle with a door,
+    /// and a teleporter between the top left and the bottom right corner.
+    /// The second snake is only used in multiplayer games.
+    pub fn classic(width: i32, height: i32) -> Level {
+        let mut walls = Vec::new();
+        let mut door_cells = HashSet::new();
+
+        for y in 0..height {
+            if (5..8).contains(&y) {
+                door_cells.insert(Point2i::new(width / 2, y));
+            } else {
+                walls.push(Point2i::new(width / 2, y));
+            }
+        }
+
+        let corner_a = Point2i::new(width - 1, height - 1);
+        let corner_b = Point2i::new(0, 0);
+
+        Level {
+            name: "Classic".to_string(),
+            width,
+            height,
+            walls,
+            doors: vec![DoorSpec { cells: door_cells, control: DoorControl::periodic(5), closing: ClosingRule::Wait }],
+            triggers: Vec::new(),
+            teleporters: vec![Teleporter::two_way(corner_a, corner_b, 0)],
+            snakes: vec![
+                SnakeStart { start: Point2i::new(width / 4, height / 2), dir: Direction::Down, length: 5 },
+                SnakeStart { start: Point2i::new(width * 3 / 4, height / 2), dir: Direction::Up, length: 5 },
+            ],
+            food: Some(Point2i::new(1, 1)),
+            food_zones: Vec::new(),
+            no_food_zones: Vec::new(),
+            border: BorderRule::Solid,
+            tail: TailRule::Follow,
+            speed: SpeedCurve::default(),
+            time_limit: None,
+        }
+    }
+
+    /// Number of field elements in a game of the level: the walls, then the doors,
+    /// teleporters and triggers.
+    pub fn element_count(&self) -> usize {
+        1 + self.doors.len() + self.teleporters.len() + self.triggers.len()
+    }
+
+    /// Fails if the file format has no symbols left for the level, instead of `Display` leaving elements
+    /// out: there are letters for 26 doors and 26 teleporter endpoints and symbols for as many switches,
+    /// plates and keys as `TRIGGER_SYMBOLS` has. Parsed levels always fit.
+    pub fn check_writable(&self) -> std::io::Result<()> {
+        let letters = ('a'..='z').count();
+        let endpoints: HashSet<Point2i> = self.teleporters.iter()
+            .flat_map(|t| std::iter::once(t.entrance).chain(t.exits.iter().copied()))
+            .collect();
+
+        let problem = if self.doors.len() > letters {
+            format!("{} doors, but a level file has letters for {}", self.doors.len(), letters)
+        } else if endpoints.len() > letters {
+            format!("{} teleporter endpoints, but a level file has letters for {}", endpoints.len(), letters)
+        } else if self.triggers.len() > TRIGGER_SYMBOLS.len() {
+            format!("{} switches, plates and keys, but a level file has symbols for {}",
+                    self.triggers.len(), TRIGGER_SYMBOLS.len())
+        } else if let Some(trigger) = self.triggers.iter().find(|t| t.door >= self.doors.len()) {
+            format!("a {} signals door {}, but there are only {} doors", trigger.kind.name(), trigger.door + 1, self.doors.len())
+        } else {
+            return Ok(());
+        };
+        Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("can't write the level: {}", problem)))
+    }
+
+    /// Whether new food may appear on `p` according to the food zones.
+    pub fn allows_food(&self, p: Point2i) -> bool {
+        (self.food_zones.is_empty() || self.food_zones.iter().any(|zone| zone.contains(p)))
+            && !self.no_food_zones.iter().any(|zone| zone.contains(p))
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Level, ParseError> {
+        Level::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(source: &str) -> Result<Level, ParseError> {
+        let mut lines = source.lines().enumerate().map(|(i, line)| (i + 1, line));
+
+        let mut name = String::new();
+        let mut snake = None;
+        let mut snake_overrides: HashMap<usize, (Direction, usize, usize, usize)> = HashMap::new();
+        let mut border = BorderRule::Solid;
+        let mut tail = TailRule::Follow;
+        let mut speed = SpeedCurve::default();
+        let mut time_limit = None;
+        let mut door_controls: HashMap<char, (DoorControl, ClosingRule, usize, usize)> = HashMap::new();
+        let mut trigger_symbols: HashMap<char, (TriggerKind, (char, usize), usize, usize)> = HashMap::new();
+        let mut teleports = Vec::new();
+        // zones along with where they were declared, checked against the size of the grid at the end
+        let mut food_zones = Vec::new();
+        let mut no_food_zones = Vec::new();
+
+        let separator_line = loop {
+            let (line_no, line) = match lines.next() {
+                Some(l) => l,
+                None => return parse_error(source.lines().count() + 1, 1, "missing '---' before the grid".to_string()),
+            };
+
+            let trimmed = line.trim();
+            if trimmed == "---" {
+                break line_no;
+            }
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            let colon = match line.find(':') {
+                Some(c) => c,
+                None => return parse_error(line_no, line.len() - line.trim_start().len() + 1,
+                                           "expected 'key: value'".to_string()),
+            };
+            let key = line[..colon].trim();
+            let key_column = line.len() - line.trim_start().len() + 1;
+            let values = tokens(line, colon + 1);
+
+            let snake_number = key.strip_prefix("snake ").map(|n| n.trim().parse::<usize>());
+
+            match key {
+                "name" => name = line[colon + 1..].trim().to_string(),
+                _ if snake_number.is_some() || key == "snake" => {
+                    expect_tokens(line_no, colon + 2, key, &values, 2)?;
+                    let dir = match Direction::from_name(values[0].1) {
+                        Some(dir) => dir,
+                        None => return parse_error(line_no, values[0].0,
+                                                   format!("unknown direction '{}'", values[0].1)),
+                    };
+                    let length = parse_number(line_no, values[1])?;
+                    if length == 0 {
+                        return parse_error(line_no, values[1].0, "snake length must be at least 1".to_string());
+                    }
+                    match snake_number {
+                        None => snake = Some((dir, length)),
+                        Some(Ok(number)) if (1..=9).contains(&number) => {
+                            snake_overrides.insert(number - 1, (dir, length, line_no, key_column));
+                        }
+                        Some(_) => return parse_error(line_no, key_column,
+                                                      format!("expected a snake number from 1 to 9 in '{}'", key)),
+                    }
+                }
+                "border" => {
+                    expect_tokens(line_no, colon + 2, key, &values, 1)?;
+                    border = match BorderRule::from_name(values[0].1) {
+                        Some(border) => border,
+                        None => return parse_error(line_no, values[0].0,
+                                                   format!("unknown border rule '{}'", values[0].1)),
+                    };
+                }
+                "tail" => {
+                    expect_tokens(line_no, colon + 2, key, &values, 1)?;
+                    tail = match TailRule::from_name(values[0].1) {
+                        Some(tail) => tail,
+                        None => return parse_error(line_no, values[0].0,
+                                                   format!("unknown tail rule '{}'", values[0].1)),
+                    };
+                }
+                "speed" if values.len() == 1 => {
+                    speed = match SpeedCurve::preset(values[0].1) {
+                        Some(speed) => speed,
+                        None => return parse_error(line_no, values[0].0,
+                                                   format!("unknown speed preset '{}'", values[0].1)),
+                    };
+                }
+                "speed" => {
+                    expect_tokens(line_no, colon + 2, key, &values, 4)?;
+                    let mut numbers = [0.0; 4];
+                    for (number, value) in numbers.iter_mut().zip(&values) {
+                        *number = match value.1.parse::<f32>() {
+                            Ok(n) if n > 0.0 => n,
+                            _ => return parse_error(line_no, value.0,
+                                                    format!("expected a positive number, found '{}'", value.1)),
+                        };
+                    }
+                    let [start, per_food, per_minute, min] = numbers;
+                    speed = SpeedCurve { start, per_food, per_minute, min };
+                }
+                "time limit" => {
+                    expect_tokens(line_no, colon + 2, key, &values, 1)?;
+                    time_limit = match parse_number(line_no, values[0])? {
+                        0 => return parse_error(line_no, values[0].0, "time limit must be at least 1 second".to_string()),
+                        seconds => Some(seconds),
+                    };
+                }
+                "door" => {
+                    if values.is_empty() {
+                        return parse_error(line_no, colon + 2, "expected '<letter> <period>'".to_string());
+                    }
+                    let id = parse_letter(line_no, values[0], true)?;
+                    let id_column = values[0].0;
+                    let (values, closing) = match &values[1..] {
+                        [rest @ .., (_, "closing"), (column, rule)] => match ClosingRule::from_name(rule) {
+                            Some(closing) => (rest, closing),
+                            None => return parse_error(line_no, *column, format!("unknown closing rule '{}'", rule)),
+                        },
+                        rest => (rest, ClosingRule::Wait),
+                    };
+                    let control = parse_door_control(line_no, id_column, values)?;
+                    if door_controls.insert(id, (control, closing, line_no, id_column)).is_some() {
+                        return parse_error(line_no, id_column, format!("door '{}' declared twice", id));
+                    }
+                }
+                "switch" | "plate" | "key" => {
+                    expect_tokens(line_no, colon + 2, key, &values, 2)?;
+                    let kind = match key {
+                        "switch" => TriggerKind::Switch,
+                        "plate" => TriggerKind::Plate,
+                        _ => TriggerKind::Key,
+                    };
+                    let symbol = parse_symbol(line_no, values[0])?;
+                    let door = (parse_letter(line_no, values[1], true)?, values[1].0);
+                    if trigger_symbols.insert(symbol, (kind, door, line_no, values[0].0)).is_some() {
+                        return parse_error(line_no, values[0].0, format!("symbol '{}' declared twice", symbol));
+                    }
+                }
+                "food zone" | "no food zone" => {
+                    expect_tokens(line_no, colon + 2, key, &values, 4)?;
+                    let mut numbers = [0; 4];
+                    for (number, value) in numbers.iter_mut().zip(&values) {
+                        *number = parse_number(line_no, *value)?;
+                    }
+                    let [_, _, width, height] = numbers;
+                    if width == 0 || height == 0 {
+                        return parse_error(line_no, values[2].0, "a zone needs at least one cell".to_string());
+                    }
+                    let zones = if key == "food zone" { &mut food_zones } else { &mut no_food_zones };
+                    zones.push((numbers, line_no, values[0].0));
+                }
+                "teleport" => {
+                    // the endpoints are single letters, the options are words
+                    let count = values.iter().take_while(|v| v.1.len() == 1).count();
+                    let mut letters = Vec::new();
+                    for &value in &values[..count] {
+                        let letter = parse_letter(line_no, value, false)?;
+                        if letters.iter().any(|l: &(char, usize)| l.0 == letter) {
+                            return parse_error(line_no, value.0, format!("teleporter '{}' leads to itself", letter));
+                        }
+                        letters.push((letter, value.0));
+                    }
+                    if letters.len() < 2 {
+                        return parse_error(line_no, values.get(count).map_or(colon + 2, |v| v.0),
+                                           "expected an entrance and at least one exit".to_string());
+                    }
+                    let options = parse_teleport_options(line_no, &values[count..])?;
+                    teleports.push((line_no, letters, options));
+                }
+                _ => return parse_error(line_no, key_column, format!("unknown key '{}'", key)),
+            }
+        };
+
+        let mut rows: Vec<(usize, &str)> = lines.map(|(line_no, line)| (line_no, line.trim_end())).collect();
+        while rows.last().is_some_and(|row| row.1.is_empty()) {
+            rows.pop();
+        }
+
+        if rows.is_empty() {
+            return parse_error(separator_line + 1, 1, "the grid is empty".to_string());
+        }
+
+        let width = rows[0].1.chars().count();
+        let mut walls = Vec::new();
+        let mut door_cells: HashMap<char, (HashSet<Point2i>, usize, usize)> = HashMap::new();
+        let mut endpoints: HashMap<char, (Point2i, usize, usize)> = HashMap::new();
+        let mut snake_starts: HashMap<usize, (Point2i, usize, usize)> = HashMap::new();
+        let mut trigger_cells: HashMap<char, Vec<Point2i>> = HashMap::new();
+        let mut food = None;
+
+        for (y, &(line_no, row)) in rows.iter().enumerate() {
+            let row_width = row.chars().count();
+            if row_width != width {
+                return parse_error(line_no, row_width.min(width) + 1,
+                                   format!("expected {} cells in this row, found {}", width, row_width));
+            }
+
+            for (x, ch) in row.chars().enumerate() {
+                let p = Point2i::new(x as i32, y as i32);
+                let column = x + 1;
+
+                match ch {
+                    '.' => (),
+                    '#' => walls.push(p),
+                    '@' | '1'..='9' => {
+                        let number = ch.to_digit(10).unwrap_or(1) as usize;
+                        if snake_starts.insert(number - 1, (p, line_no, column)).is_some() {
+                            return parse_error(line_no, column, format!("second start of snake {}", number));
+                        }
+                    }
+                    '*' => {
+                        if food.replace(p).is_some() {
+                            return parse_error(line_no, column, "second food '*'".to_string());
+                        }
+                    }
+                    'A'..='Z' => {
+                        door_cells.entry(ch).or_insert_with(|| (HashSet::new(), line_no, column)).0.insert(p);
+                    }
+                    'a'..='z' => {
+                        if endpoints.insert(ch, (p, line_no, column)).is_some() {
+                            return parse_error(line_no, column, format!("teleporter endpoint '{}' used twice", ch));
+                        }
+                    }
+                    _ if trigger_symbols.contains_key(&ch) => trigger_cells.entry(ch).or_default().push(p),
+                    _ => return parse_error(line_no, column, format!("unknown cell '{}'", ch)),
+                }
+            }
+        }
+
+        let end_line = rows.last().unwrap().0 + 1;
+
+        if snake_starts.is_empty() {
+            return parse_error(end_line, 1, "the grid has no snake start '@'".to_string());
+        }
+
+        let mut snakes = Vec::new();
+        for index in 0..snake_starts.len() {
+            let start = match snake_starts.get(&index) {
+                Some(start) => start.0,
+                None => {
+                    let (_, line_no, column) = snake_starts[snake_starts.keys().max().unwrap()];
+                    return parse_error(line_no, column, format!("snake {} has no start position", index + 1));
+                }
+            };
+
+            let (dir, length) = match (snake_overrides.remove(&index), snake) {
+                (Some((dir, length, _, _)), _) => (dir, length),
+                (None, Some(s)) => s,
+                (None, None) => return parse_error(separator_line, 1, "missing 'snake: <direction> <length>'".to_string()),
+            };
+            snakes.push(SnakeStart { start, dir, length });
+        }
+
+        if let Some((index, &(_, _, line_no, column))) = snake_overrides.iter().min_by_key(|(_, o)| (o.2, o.3)) {
+            return parse_error(line_no, column, format!("snake {} has no start position in the grid", index + 1));
+        }
+
+        let mut declared: Vec<_> = door_controls.into_iter().collect();
+        declared.sort_by_key(|d| d.0);
+
+        let mut doors = Vec::new();
+        let mut door_index = HashMap::new();
+        for (id, (control, closing, line_no, column)) in declared {
+            match door_cells.remove(&id) {
+                Some((cells, _, _)) => {
+                    door_index.insert(id, doors.len());
+                    doors.push(DoorSpec { cells, control, closing });
+                }
+                None => return parse_error(line_no, column, format!("door '{}' has no cells in the grid", id)),
+            }
+        }
+        if let Some((&id, &(_, line_no, column))) = door_cells.iter().min_by_key(|(_, d)| (d.1, d.2)) {
+            return parse_error(line_no, column, format!("door '{}' is not declared, add 'door: {} <period>'", id, id));
+        }
+
+        let mut symbols: Vec<_> = trigger_symbols.into_iter().collect();
+        symbols.sort_by_key(|(_, t)| t.2);
+
+        let mut triggers = Vec::new();
+        for (symbol, (kind, (door, door_column), line_no, column)) in symbols {
+            let door = match door_index.get(&door) {
+                Some(&index) => index,
+                None => return parse_error(line_no, door_column, format!("door '{}' is not declared", door)),
+            };
+            if !matches!(doors[door].control, DoorControl::Switch | DoorControl::Key) {
+                return parse_error(line_no, door_column,
+                                   format!("{} '{}' signals a door which is neither a 'switch' nor a 'key' door",
+                                           kind.name(), symbol));
+            }
+            match trigger_cells.remove(&symbol) {
+                Some(cells) => triggers.push(TriggerSpec { kind, cells, door }),
+                None => return parse_error(line_no, column, format!("{} '{}' has no cells in the grid", kind.name(), symbol)),
+            }
+        }
+
+        let mut teleporters = Vec::new();
+        let mut used = HashSet::new();
+        for (line_no, letters, options) in teleports {
+            for &(letter, column) in &letters {
+                if !endpoints.contains_key(&letter) {
+                    return parse_error(line_no, column, format!("teleporter endpoint '{}' is not in the grid", letter));
+                }
+                if !used.insert(letter) {
+                    return parse_error(line_no, column, format!("teleporter endpoint '{}' belongs to another teleporter", letter));
+                }
+            }
+
+            let exits = letters[1..].iter().map(|(letter, _)| endpoints[letter].0).collect();
+            let mut teleporter = Teleporter::new(endpoints[&letters[0].0].0, exits, teleporters.len());
+            teleporter.two_way &= !options.one_way;
+            teleporter.choice = options.choice;
+            teleporter.exit_direction = options.exit_direction;
+            teleporter.cooldown = options.cooldown;
+            teleporter.carries_food = options.carries_food;
+            teleporters.push(teleporter);
+        }
+        if let Some((&letter, &(_, line_no, column))) = endpoints.iter()
+            .filter(|(l, _)| !used.contains(*l))
+            .min_by_key(|(_, e)| (e.1, e.2)) {
+            return parse_error(line_no, column, format!("teleporter endpoint '{}' is not connected, add 'teleport: {} <letter>'", letter, letter));
+        }
+
+        for &([x, y, zone_width, zone_height], line_no, column) in food_zones.iter().chain(&no_food_zones) {
+            let fits = |start: usize, size: usize, limit: usize| start.checked_add(size).is_some_and(|end| end <= limit);
+            if !fits(x, zone_width, width) || !fits(y, zone_height, rows.len()) {
+                return parse_error(line_no, column, "the zone reaches outside of the grid".to_string());
+            }
+        }
+        // every zone fits into the grid, so its numbers fit into an i32
+        let to_zone = |([x, y, width, height], _, _): ([usize; 4], usize, usize)| {
+            Zone { x: x as i32, y: y as i32, width: width as i32, height: height as i32 }
+        };
+
+        Ok(Level {
+            name,
+            width: width as i32,
+            height: rows.len() as i32,
+            walls,
+            doors,
+            triggers,
+            teleporters,
+            snakes,
+            food,
+            food_zones: food_zones.into_iter().map(to_zone).collect(),
+            no_food_zones: no_food_zones.into_iter().map(to_zone).collect(),
+            border,
+            tail,
+            speed,
+            time_limit,
+        })
+    }
+}
+
+/// Writes the level in the file format understood by `Level::parse`.
+/// Fails for levels `check_writable` rejects.
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.check_writable().is_err() {
+            return Err(fmt::Error);
+        }
+        let mut grid = vec![vec!['.'; self.width as usize]; self.height as usize];
+        let mut set = |p: Point2i, ch: char| grid[p.y as usize][p.x as usize] = ch;
+
+        writeln!(f, "name: {}", self.name)?;
+        for (i, snake) in self.snakes.iter().enumerate() {
+            if i == 0 {
+                writeln!(f, "snake: {} {}", snake.dir.name(), snake.length)?;
+            } else {
+                writeln!(f, "snake {}: {} {}", i + 1, snake.dir.name(), snake.length)?;
+            }
+        }
+        writeln!(f, "border: {}", self.border.name())?;
+        writeln!(f, "tail: {}", self.tail.name())?;
+        match self.speed.preset_name() {
+            Some(name) => writeln!(f, "speed: {}", name)?,
+            None => writeln!(f, "speed: {} {} {} {}",
+                             self.speed.start, self.speed.per_food, self.speed.per_minute, self.speed.min)?,
+        }
+        if let Some(seconds) = self.time_limit {
+            writeln!(f, "time limit: {}", seconds)?;
+        }
+
+        for (key, zones) in [("food zone", &self.food_zones), ("no food zone", &self.no_food_zones)] {
+            for zone in zones {
+                writeln!(f, "{}: {} {} {} {}", key, zone.x, zone.y, zone.width, zone.height)?;
+            }
+        }
+
+        for wall in &self.walls {
+            set(*wall, '#');
+        }
+
+        for (door, id) in self.doors.iter().zip('A'..='Z') {
+            let control = match &door.control {
+                DoorControl::Timer { phases, offset } => {
+                    let phases = match phases[..] {
+                        [(false, closed), (true, open)] if closed == open => (closed - 1).to_string(),
+                        [(false, closed), (true, open)] => format!("timer {} {}", closed, open),
+                        _ => {
+                            let pattern: Vec<String> = phases.iter()
+                                .map(|(open, steps)| format!("{}{}", if *open { 'o' } else { 'c' }, steps))
+                                .collect();
+                            format!("pattern {}", pattern.join(" "))
+                        }
+                    };
+                    if *offset > 0 { format!("{} phase {}", phases, offset) } else { phases }
+                }
+                DoorControl::Switch => "switch".to_string(),
+                DoorControl::Key => "key".to_string(),
+                DoorControl::Food(count) => format!("food {}", count),
+            };
+            match door.closing {
+                ClosingRule::Wait => writeln!(f, "door: {} {}", id, control)?,
+                closing => writeln!(f, "door: {} {} closing {}", id, control, closing.name())?,
+            }
+            for cell in &door.cells {
+                set(*cell, id);
+            }
+        }
+
+        for (trigger, symbol) in self.triggers.iter().zip(TRIGGER_SYMBOLS.chars()) {
+            writeln!(f, "{}: {} {}", trigger.kind.name(), symbol, (b'A' + trigger.door as u8) as char)?;
+            for cell in &trigger.cells {
+                set(*cell, symbol);
+            }
+        }
+
+        let mut endpoints: Vec<Point2i> = Vec::new();
+        let letter = |p: Point2i, endpoints: &mut Vec<Point2i>| {
+            let index = endpoints.iter().position(|e| *e == p).unwrap_or_else(|| {
+                endpoints.push(p);
+                endpoints.len() - 1
+            });
+            (b'a' + index as u8) as char
+        };
+
+        for t in &self.teleporters {
+            let mut entry = format!("teleport: {}", letter(t.entrance, &mut endpoints));
+            for exit in &t.exits {
+                entry.push_str(&format!(" {}", letter(*exit, &mut endpoints)));
+            }
+            if !t.two_way && t.exits.len() == 1 {
+                entry.push_str(" oneway");
+            }
+            if t.choice == ExitChoice::Random {
+                entry.push_str(" random");
+            }
+            match t.exit_direction {
+                ExitDirection::Keep => (),
+                ExitDirection::TurnLeft => entry.push_str(" turn left"),
+                ExitDirection::TurnRight => entry.push_str(" turn right"),
+                ExitDirection::Reverse => entry.push_str(" turn back"),
+                ExitDirection::Fixed(dir) => entry.push_str(&format!(" exit {}", dir.name())),
+            }
+            if t.cooldown > 0 {
+                entry.push_str(&format!(" cooldown {}", t.cooldown));
+            }
+            if t.carries_food {
+                entry.push_str(" food");
+            }
+            writeln!(f, "{}", entry)?;
+        }
+
+        for (i, p) in endpoints.iter().enumerate() {
+            set(*p, (b'a' + i as u8) as char);
+        }
+
+        if let Some(food) = self.food {
+            set(food, '*');
+        }
+        for (i, snake) in self.snakes.iter().enumerate() {
+            set(snake.start, if i == 0 { '@' } else { (b'1' + i as u8) as char });
+        }
+
+        writeln!(f, "---")?;
+        for row in grid {
+            writeln!(f, "{}", row.into_iter().collect::<String>())?;
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Where parsing `source` fails, along with the message.
+    fn error_at(source: &str) -> (usize, usize, String) {
+        match Level::parse(source) {
+            Err(ParseError::Parse { line, column, message }) => (line, column, message),
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("parsed without an error"),
+        }
+    }
+
+    fn position(source: &str) -> (usize, usize) {
+        let (line, column, _) = error_at(source);
+        (line, column)
+    }
+
+    #[test]
+    fn malformed_headers() {
+        assert_eq!(position("snake right 3\n---\n@..\n"), (1, 1));
+        assert_eq!(position("name: Test\n  snake right 3\n---\n@..\n"), (2, 3));
+        assert_eq!(position("snake: right 3\nlives: 3\n---\n@..\n"), (2, 1));
+        assert_eq!(position("snake: right\n---\n@..\n"), (1, 7));
+        assert_eq!(position("snake: right 3 4\n---\n@..\n"), (1, 16));
+        assert_eq!(position("snake: sideways 3\n---\n@..\n"), (1, 8));
+        assert_eq!(position("snake: right 3\nborder: rubber\n---\n@..\n"), (2, 9));
+        assert_eq!(position("snake: right 3\n"), (2, 1));
+    }
+
+    #[test]
+    fn unknown_tiles() {
+        let (line, column, message) = error_at("snake: right 1\n---\n...\n.@?\n");
+        assert_eq!((line, column), (4, 3));
+        assert_eq!(message, "unknown cell '?'");
+
+        // a trigger symbol that was never declared
+        assert_eq!(position("snake: right 1\n---\n@..\n..!\n"), (4, 3));
+        // a door letter without a `door` entry
+        assert_eq!(position("snake: right 1\n---\n@.B\n...\n"), (3, 3));
+        assert_eq!(position("snake: right 1\n---\n@..\n....\n"), (4, 4));
+    }
+
+    #[test]
+    fn out_of_range_numbers() {
+        assert_eq!(position("snake: right x\n---\n@..\n"), (1, 14));
+        assert_eq!(position("snake: right 0\n---\n@..\n"), (1, 14));
+        assert_eq!(position("snake: right -2\n---\n@..\n"), (1, 14));
+        assert_eq!(position("snake: right 99999999999999999999999\n---\n@..\n"), (1, 14));
+        assert_eq!(position("snake 12: right 1\n---\n@..\n"), (1, 1));
+
+        let door = |entry: &str| position(&format!("snake: right 1\ndoor: {}\n---\n@A.\n", entry));
+        assert_eq!(door(&format!("A {}", usize::MAX)), (2, 9));
+        assert_eq!(door("A 1000001"), (2, 9));
+        assert_eq!(door("A timer 0 3"), (2, 15));
+        assert_eq!(door("A timer 3 1000001"), (2, 17));
+        assert_eq!(door("A pattern c4 o2000000"), (2, 21));
+        assert_eq!(door("A timer 3 3 phase 1000001"), (2, 25));
+        assert!(Level::parse("snake: right 1\ndoor: A 1000000\n---\n@A.\n").is_ok());
+    }
+
+    #[test]
+    fn bad_zones() {
+        let zone = |entry: &str| error_at(&format!("snake: right 1\n{}\n---\n@..\n...\n", entry));
+
+        assert_eq!(zone("food zone: 1 1 3 1"), (2, 12, "the zone reaches outside of the grid".to_string()));
+        assert_eq!(zone("no food zone: 0 1 1 2").0, 2);
+        assert_eq!(zone("food zone: 1 1 0 1"), (2, 16, "a zone needs at least one cell".to_string()));
+        assert_eq!(zone("food zone: 1 1 1").0, 2);
+        let (line, column, _) = zone("food zone: 1 one 1 1");
+        assert_eq!((line, column), (2, 14));
+    }
+
+    #[test]
+    fn zones_too_large_to_add_up() {
+        let max = usize::MAX;
+        let (line, column, _) = error_at(&format!("snake: right 1\nfood zone: {} 0 {} 1\n---\n@..\n", max, max));
+        assert_eq!((line, column), (2, 12));
+        let (line, column, _) = error_at(&format!("snake: right 1\nfood zone: 0 1 1 {}\n---\n@..\n", max));
+        assert_eq!((line, column), (2, 12));
+    }
+
+    #[test]
+    fn display_round_trips_shipped_levels() {
+        let mut count = 0;
+        for entry in fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/levels")).unwrap() {
+            let path = entry.unwrap().path();
+            let level = Level::from_file(&path).unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+            let written = level.to_string();
+            let reparsed = Level::parse(&written).unwrap_or_else(|err| panic!("{}: {}\n{}", path.display(), err, written));
+
+            assert_eq!(reparsed.to_string(), written, "{}", path.display());
+            assert_eq!((reparsed.width, reparsed.height), (level.width, level.height));
+            assert_eq!(reparsed.walls.len(), level.walls.len());
+            assert_eq!(reparsed.doors.len(), level.doors.len());
+            assert_eq!(reparsed.teleporters.len(), level.teleporters.len());
+            assert_eq!(reparsed.snakes.len(), level.snakes.len());
+            count += 1;
+        }
+        assert!(count >= 4);
+    }
+
+    #[test]
+    fn levels_beyond_the_symbols_are_not_written() {
+        use std::fmt::Write;
+        let written = |level: &Level| write!(String::new(), "{}", level).is_ok();
+
+        let mut level = Level::classic(20, 20);
+        assert!(level.check_writable().is_ok() && written(&level));
+        let door = level.doors[0].clone();
+        level.doors.resize(26, door.clone());
+        assert!(level.check_writable().is_ok());
+        level.doors.push(door);
+        assert!(level.check_writable().is_err() && !written(&level));
+
+        let mut level = Level::classic(40, 20);
+        level.teleporters = (0..14).map(|i| Teleporter::two_way(Point2i::new(i, 0), Point2i::new(i, 19), i as usize)).collect();
+        let message = level.check_writable().unwrap_err().to_string();
+        assert_eq!(message, "can't write the level: 28 teleporter endpoints, but a level file has letters for 26");
+        assert!(!written(&level));
+
+        let mut level = Level::classic(20, 20);
+        level.triggers.push(TriggerSpec { kind: TriggerKind::Switch, cells: vec![Point2i::new(1, 1)], door: 1 });
+        assert!(level.check_writable().is_err() && !written(&level));
+    }
+}
@@ -0,0 +1,15 @@
+//! Simulation of the snake game without any graphics, sound or windowing dependencies.
+//!
+//! The `rust_snake` binary is a thin piston frontend on top of this library. Headless tools
+//! can depend on the crate with `default-features = false` to get only the simulation.
+
+pub mod ai;
+pub mod engine;
+pub mod field;
+pub mod food;
+pub mod level;
+pub mod occupancy;
+pub mod parse;
+pub mod replay;
+pub mod save;
+pub mod snake;
@@ -1,46 +1,272 @@
-mod engine;
 mod game;
+mod hud;
+mod minimap;
+mod pause_menu;
+mod replay_viewer;
 mod sound;
+mod sprites;
 mod text_helpers;
+mod theme;
+mod view;
 
 extern crate piston_window;
 extern crate find_folder;
 
 use piston_window::*;
-use crate::ActiveScreen::{MainGame, InitScreen, LooseScreen};
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::ActiveScreen::{MainGame, InitScreen, LooseScreen, ReplayScreen, SettingsScreen, VictoryScreen};
+use winit::window::Fullscreen;
 #[cfg(target_os = "windows")]
 use winit::window::Icon;
 #[cfg(target_os = "windows")]
 use winit::platform::windows::IconExtWindows;
 
 
-use crate::engine::GameEvent;
+use rust_snake::ai::{Autopilot, Strategy};
+use rust_snake::engine::{BorderRule, Game, TailRule};
+use rust_snake::level::Level;
+use rust_snake::replay::Replay;
+use rust_snake::save::SaveGame;
+
+use crate::pause_menu::{PauseChoice, PauseMenu};
+use crate::replay_viewer::ReplayViewer;
 use crate::sound::Sound;
-use crate::text_helpers::draw_text_multiline;
+use crate::text_helpers::{draw_text_multiline, scaled, screen_scale};
+use crate::theme::{Skin, Theme};
 
 
 enum ActiveScreen {
     MainGame(game::MainGame),
     InitScreen,
-    LooseScreen(u32),
+    LooseScreen(String),
+    /// Shown when the snakes filled the whole board.
+    VictoryScreen(String),
+    ReplayScreen(ReplayViewer),
+    /// Lets the player pick a theme, then returns to the paused game if there is one.
+    SettingsScreen(Option<game::MainGame>),
+}
+
+const REPLAY_DIR: &str = "replays";
+const LAST_REPLAY: &str = "last.replay";
+const SAVE_DIR: &str = "saves";
+const SAVE_FILE: &str = "resume.save";
+
+fn save_recording(game: &mut game::MainGame) {
+    if let Some(replay) = game.take_recording() {
+        let dir = Path::new(REPLAY_DIR);
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let path = dir.join(format!("run-{}.replay", timestamp));
+
+        let result = fs::create_dir_all(dir)
+            .and_then(|_| replay.save(&path))
+            .and_then(|_| replay.save(dir.join(LAST_REPLAY)));
+
+        match result {
+            Ok(()) => println!("Saved replay to {}", path.display()),
+            Err(err) => eprintln!("Failed to save replay: {}", err),
+        }
+    }
+}
+
+/// Saves a game that was quit before it was over, so it can be resumed from the main menu.
+fn save_game(game: &game::MainGame) -> bool {
+    let dir = Path::new(SAVE_DIR);
+    let result = fs::create_dir_all(dir)
+        .and_then(|_| game.save_state().save(dir.join(SAVE_FILE)));
+
+    match result {
+        Ok(()) => true,
+        Err(err) => {
+            eprintln!("Failed to save game: {}", err);
+            false
+        }
+    }
+}
+
+/// Loads a saved game, paused to give the player time to find their snake.
+fn load_game<P: AsRef<Path>>(path: P) -> Option<game::MainGame> {
+    match SaveGame::from_file(&path) {
+        Ok(save) => Some(resume_game(&save)),
+        Err(err) => {
+            eprintln!("Failed to load saved game {}: {}", path.as_ref().display(), err);
+            None
+        }
+    }
+}
+
+fn load_replay<P: AsRef<Path>>(path: P) -> Option<Replay> {
+    match Replay::from_file(&path) {
+        Ok(replay) => Some(replay),
+        Err(err) => {
+            eprintln!("Failed to load replay {}: {}", path.as_ref().display(), err);
+            None
+        }
+    }
+}
+
+fn resume_game(save: &SaveGame) -> game::MainGame {
+    let mut game = game::MainGame::from_game(Game::from_save(save));
+    for &(player, strategy) in &save.autopilots {
+        game.add_autopilot(Autopilot::new(player, strategy));
+    }
+    game.pause();
+    game
+}
+
+/// A new game on `level` with the settings of the main menu.
+fn new_game(level: &Level, players: usize, cpu_opponents: bool, seed: Option<u64>, high_score: usize) -> game::MainGame {
+    let mut game_level = level.clone();
+    game_level.snakes.truncate(players);
+    let mut main_game = game::MainGame::new(&game_level, seed);
+    main_game.set_high_score(high_score);
+    if cpu_opponents {
+        for player in 1..players {
+            main_game.add_autopilot(Autopilot::new(player, Strategy::Greedy));
+        }
+    }
+    main_game
+}
+
+/// A game played by the computer in the background of the main screen.
+fn attract_demo(level: &Level) -> game::MainGame {
+    let mut demo = game::MainGame::from_game(Game::from_level(level));
+    for player in 0..level.snakes.len() {
+        demo.add_autopilot(Autopilot::new(player, Strategy::Greedy));
+    }
+    demo
+}
+
+/// Parses a board size like `30x20`.
+fn parse_board_size(size: &str) -> Option<(i32, i32)> {
+    let (width, height) = size.split_once('x')?;
+    let (width, height) = (width.parse().ok()?, height.parse().ok()?);
+    // the classic layout needs room for its door and the snakes
+    if width < 10 || height < 10 {
+        return None;
+    }
+    Some((width, height))
+}
+
+fn toggle_fullscreen(window: &PistonWindow) {
+    let window = window.window.ctx.window();
+    let fullscreen = match window.fullscreen() {
+        Some(_) => None,
+        None => Some(Fullscreen::Borderless(None)),
+    };
+    window.set_fullscreen(fullscreen);
 }
 
 fn main() {
-    let rect_size = 20.0;
-    let field_size = 20.0;
+    let mut board_size = (20, 20);
 
-    let mut window: PistonWindow =
-        WindowSettings::new("Rust Snake", [rect_size * field_size; 2])
-            .build().unwrap();
+    let mut level_path = None;
+    let mut seed = None;
+    let mut replay = None;
+    let mut load = None;
+    let mut rectangles = false;
+    let mut theme_name = None;
+    let mut fullscreen = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => {
+                seed = Some(args.next().and_then(|s| s.parse().ok()).unwrap_or_else(|| {
+                    eprintln!("--seed expects a number");
+                    std::process::exit(1);
+                }));
+            }
+            "--replay" => {
+                let path = args.next().unwrap_or_else(|| {
+                    eprintln!("--replay expects a file");
+                    std::process::exit(1);
+                });
+                replay = Some(load_replay(path).unwrap_or_else(|| std::process::exit(1)));
+            }
+            "--load" => {
+                let path = args.next().unwrap_or_else(|| {
+                    eprintln!("--load expects a file");
+                    std::process::exit(1);
+                });
+                load = Some(SaveGame::from_file(&path).unwrap_or_else(|err| {
+                    eprintln!("Failed to load saved game {}: {}", path, err);
+                    std::process::exit(1);
+                }));
+            }
+            "--size" => {
+                board_size = args.next().as_deref().and_then(parse_board_size).unwrap_or_else(|| {
+                    eprintln!("--size expects the board size as <width>x<height>, e.g. 30x20");
+                    std::process::exit(1);
+                });
+            }
+            "--fullscreen" => fullscreen = true,
+            "--rectangles" => rectangles = true,
+            "--theme" => {
+                theme_name = Some(args.next().unwrap_or_else(|| {
+                    eprintln!("--theme expects a theme name");
+                    std::process::exit(1);
+                }));
+            }
+            _ => level_path = Some(arg),
+        }
+    }
+
+    let mut level = match (level_path, &replay, &load) {
+        (Some(path), _, _) => Level::from_file(&path).unwrap_or_else(|err| {
+            eprintln!("Failed to load level {}: {}", path, err);
+            std::process::exit(1);
+        }),
+        (None, Some(replay), _) => replay.level.clone(),
+        (None, None, Some(save)) => save.level.clone(),
+        (None, None, None) => Level::classic(board_size.0, board_size.1),
+    };
+
+    // 20 pixels per cell to begin with, smaller for boards that would not fit on a screen,
+    // the window can be resized afterwards
+    let rect_size = (1000.0 / level.width.max(level.height) as f64).floor().clamp(4.0, 20.0);
 
     let assets = find_folder::Search::ParentsThenKids(3, 3)
         .for_folder("assets").unwrap();
     println!("Found assets: {:?}", assets);
-    let mut glyphs = window.load_font(assets.join("FiraSans-Regular.ttf")).unwrap();
+
+    let themes = Theme::load_all(assets.join("themes"));
+    let mut theme_index = match theme_name {
+        Some(name) => themes.iter().position(|t| t.name.eq_ignore_ascii_case(&name)).unwrap_or_else(|| {
+            let names: Vec<&str> = themes.iter().map(|t| t.name.as_str()).collect();
+            eprintln!("Unknown theme '{}', available themes: {}", name, names.join(", "));
+            std::process::exit(1);
+        }),
+        None => 0,
+    };
+
+    let hud_size = hud::size(&themes[theme_index]);
+    let window_size = [rect_size * level.width as f64 + hud_size[0],
+                       rect_size * level.height as f64 + hud_size[1]];
+    let mut window: PistonWindow =
+        WindowSettings::new(format!("Rust Snake - {}", level.name), window_size)
+            .resizable(true)
+            .fullscreen(fullscreen)
+            .build().unwrap();
+
+    let mut skin = Skin::load(&mut window, &assets, themes[theme_index].clone(), rectangles);
 
     let sound = Sound::new(&assets);
 
-    let mut active = InitScreen;
+
+    let mut players = 1;
+    let mut cpu_opponents = false;
+    let mut demo: Option<game::MainGame> = None;
+    let mut has_save = Path::new(SAVE_DIR).join(SAVE_FILE).exists();
+    // best score of all games played since the start
+    let mut high_score = 0;
+    let mut pause_menu = PauseMenu::default();
+
+    let mut active = match (replay, load) {
+        (Some(replay), _) => ReplayScreen(ReplayViewer::new(replay)),
+        (None, Some(save)) => MainGame(resume_game(&save)),
+        (None, None) => InitScreen,
+    };
 
     #[cfg(target_os = "windows")]
     {
@@ -50,29 +276,136 @@ fn main() {
     }
 
     while let Some(e) = window.next() {
+        if let Some(Button::Keyboard(Key::F11)) = e.press_args() {
+            toggle_fullscreen(&window);
+        }
+
         match &mut active {
             MainGame(ref mut game) => {
-                if let Some(button) = e.press_args() {
-                    match button {
-                        Button::Keyboard(Key::Escape) => {
+                if e.focus_args() == Some(false) && !game.is_paused() {
+                    game.pause();
+                    pause_menu = PauseMenu::default();
+                }
+
+                match e.press_args() {
+                    Some(Button::Keyboard(key)) if game.is_paused() => match pause_menu.handle_key(key) {
+                        Some(PauseChoice::Resume) => game.resume(),
+                        Some(PauseChoice::Restart) => {
+                            high_score = high_score.max(game.best_score());
+                            save_recording(game);
+                            // a resumed game may be on another level than the one of the menu
+                            active = MainGame(game.restart(seed));
+                            continue;
+                        }
+                        Some(PauseChoice::Settings) => {
+                            if let MainGame(game) = std::mem::replace(&mut active, InitScreen) {
+                                active = SettingsScreen(Some(game));
+                            }
+                            continue;
+                        }
+                        Some(PauseChoice::Quit) => {
+                            high_score = high_score.max(game.best_score());
+                            save_recording(game);
+                            has_save = save_game(game);
                             active = InitScreen;
                             continue;
                         }
-                        _ => (),
+                        None => (),
+                    },
+                    Some(Button::Keyboard(Key::Escape | Key::P | Key::Pause)) => {
+                        game.pause();
+                        pause_menu = PauseMenu::default();
                     }
+                    _ => (),
                 }
 
-                let game_result = game.run(&mut window, &mut glyphs, e);
-                sound.play_for_event(&game_result);
-                if let GameEvent::Collision = game_result {
-                    active = LooseScreen(game.current_score());
+                let events = game.run(&mut window, &mut skin, e.clone());
+                sound.play_for_events(&events);
+                if game.is_paused() {
+                    let theme = &skin.theme;
+                    let glyphs = &mut skin.glyphs;
+                    window.draw_2d(&e, |c, g, device| {
+                        pause_menu.draw(theme, glyphs, &c, g);
+
+                        // Update glyphs before rendering.
+                        glyphs.factory.encoder.flush(device);
+                    });
+                }
+                if game.is_over() {
+                    high_score = high_score.max(game.best_score());
+                    save_recording(game);
+                    active = if game.is_won() {
+                        VictoryScreen(game.result_text())
+                    } else {
+                        LooseScreen(game.result_text())
+                    };
                 }
             }
+            ReplayScreen(ref mut viewer) => {
+                if let Some(Button::Keyboard(Key::Escape)) = e.press_args() {
+                    active = InitScreen;
+                    continue;
+                }
+
+                viewer.run(&mut window, &mut skin, &sound, e);
+            }
             InitScreen => {
                 if let Some(button) = e.press_args() {
                     match button {
                         Button::Keyboard(Key::Return) => {
-                            active = MainGame(game::MainGame::new(rect_size, field_size));
+                            active = MainGame(new_game(&level, players, cpu_opponents, seed, high_score));
+                            demo = None;
+                            continue;
+                        }
+                        Button::Keyboard(Key::P) => {
+                            players = players % level.snakes.len() + 1;
+                        }
+                        Button::Keyboard(Key::C) => {
+                            cpu_opponents = !cpu_opponents;
+                        }
+                        Button::Keyboard(Key::S) => {
+                            level.speed = level.speed.next_preset();
+                            demo = None;
+                        }
+                        Button::Keyboard(Key::B) => {
+                            level.border = match level.border {
+                                BorderRule::Solid => BorderRule::Wrap,
+                                BorderRule::Wrap => BorderRule::Bounce,
+                                BorderRule::Bounce => BorderRule::Solid,
+                            };
+                            demo = None;
+                        }
+                        Button::Keyboard(Key::T) => {
+                            level.tail = match level.tail {
+                                TailRule::Follow => TailRule::Block,
+                                TailRule::Block => TailRule::Follow,
+                            };
+                            demo = None;
+                        }
+                        Button::Keyboard(Key::O) => {
+                            active = SettingsScreen(None);
+                            continue;
+                        }
+                        Button::Keyboard(Key::R) => {
+                            if let Some(replay) = load_replay(Path::new(REPLAY_DIR).join(LAST_REPLAY)) {
+                                active = ReplayScreen(ReplayViewer::new(replay));
+                                continue;
+                            }
+                        }
+                        Button::Keyboard(Key::L) if has_save => {
+                            let path = Path::new(SAVE_DIR).join(SAVE_FILE);
+                            if let Some(mut resumed) = load_game(&path) {
+                                resumed.set_high_score(high_score);
+                                pause_menu = PauseMenu::default();
+                                // a save can only be resumed once
+                                if let Err(err) = fs::remove_file(&path) {
+                                    eprintln!("Failed to remove saved game: {}", err);
+                                }
+                                has_save = false;
+                                active = MainGame(resumed);
+                                demo = None;
+                                continue;
+                            }
                         }
                         Button::Keyboard(Key::Escape) => {
                             return;
@@ -81,24 +414,93 @@ fn main() {
                     }
                 }
 
+                let attract = match &mut demo {
+                    Some(attract) if !attract.is_over() => attract,
+                    _ => demo.insert(attract_demo(&level)),
+                };
+                attract.run(&mut window, &mut skin, e.clone());
+
+                let theme = &skin.theme;
+                let glyphs = &mut skin.glyphs;
                 window.draw_2d(&e, |c, g, device| {
-                    clear([0.95, 0.95, 0.95, 1.0], g);
+                    let [width, height] = c.get_view_size();
+                    let scale = screen_scale([width, height]);
+                    rectangle(theme.overlay, [0.0, 0.0, width, height], c.transform, g);
 
-                    draw_text_multiline([0.0, 0.0, 0.0, 1.0], 64, "Snake Game", Size::from([rect_size * field_size, 100.0]),
-                                        &mut glyphs, c.transform, g,
+                    draw_text_multiline(theme.text, scaled(64, scale), "Snake Game", Size::from([width, 100.0 * scale]),
+                                        glyphs, c.transform, g,
                     ).unwrap();
 
-                    draw_text_multiline([0.0, 0.8, 0.0, 1.0], 48, "Press enter\nto start!", Size::from([rect_size * field_size, rect_size * field_size - 100.0]),
-                                        &mut glyphs, c.transform.trans(0.0, 100.0), g,
+                    draw_text_multiline(theme.highlight, scaled(48, scale), "Press enter\nto start!",
+                                        Size::from([width, height - 160.0 * scale]),
+                                        glyphs, c.transform.trans(0.0, 100.0 * scale), g,
+                    ).unwrap();
+
+                    let cpu = if cpu_opponents { "on" } else { "off" };
+                    let resume = if has_save { "   L: resume" } else { "" };
+                    let speed = level.speed.preset_name().unwrap_or("custom");
+                    let hint = format!("P: players ({})   C: CPU opponents ({})\nB: border ({})   T: tail ({})\nS: speed ({})   O: settings\nR: watch last run{}",
+                                       players, cpu, level.border.name(), level.tail.name(), speed, resume);
+                    draw_text_multiline(theme.hint, scaled(20, scale), &hint, Size::from([width, 115.0 * scale]),
+                                        glyphs, c.transform.trans(0.0, height - 115.0 * scale), g,
+                    ).unwrap();
+
+
+                    // Update glyphs before rendering.
+                    glyphs.factory.encoder.flush(device);
+                });
+            }
+            SettingsScreen(paused_game) => {
+                if let Some(Button::Keyboard(key)) = e.press_args() {
+                    let selected = match key {
+                        Key::Up => (theme_index + themes.len() - 1) % themes.len(),
+                        Key::Down => (theme_index + 1) % themes.len(),
+                        Key::Return | Key::Escape => {
+                            active = match paused_game.take() {
+                                Some(game) => MainGame(game),
+                                None => InitScreen,
+                            };
+                            continue;
+                        }
+                        _ => theme_index,
+                    };
+                    if selected != theme_index {
+                        theme_index = selected;
+                        skin = Skin::load(&mut window, &assets, themes[theme_index].clone(), rectangles);
+                    }
+                }
+
+                let theme = &skin.theme;
+                let glyphs = &mut skin.glyphs;
+                window.draw_2d(&e, |c, g, device| {
+                    clear(theme.background, g);
+
+                    let [width, height] = c.get_view_size();
+                    let scale = screen_scale([width, height]);
+                    draw_text_multiline(theme.text, scaled(48, scale), "Settings", Size::from([width, 100.0 * scale]),
+                                        glyphs, c.transform, g,
                     ).unwrap();
 
+                    // the selected theme is shown in the highlight colour, the others like hints
+                    for (i, other) in themes.iter().enumerate() {
+                        let color = if i == theme_index { theme.highlight } else { theme.hint };
+                        draw_text_multiline(color, scaled(28, scale), &other.name, Size::from([width, 36.0 * scale]),
+                                            glyphs, c.transform.trans(0.0, (100.0 + 36.0 * i as f64) * scale), g,
+                        ).unwrap();
+                    }
+
+                    draw_text_multiline(theme.hint, scaled(20, scale), "Up/Down: theme\nEnter: back",
+                                        Size::from([width, 60.0 * scale]),
+                                        glyphs, c.transform.trans(0.0, height - 60.0 * scale), g,
+                    ).unwrap();
 
                     // Update glyphs before rendering.
                     glyphs.factory.encoder.flush(device);
                 });
             }
-            LooseScreen(score) => {
-                let score = *score;
+            LooseScreen(result) | VictoryScreen(result) => {
+                let result = result.clone();
+                let won = matches!(active, VictoryScreen(_));
 
                 if let Some(button) = e.press_args() {
                     match button {
@@ -112,13 +514,17 @@ fn main() {
                     }
                 }
 
+                let theme = &skin.theme;
+                let glyphs = &mut skin.glyphs;
                 window.draw_2d(&e, |c, g, device| {
-                    clear([0.95, 0.95, 0.95, 1.0], g);
-
+                    clear(theme.background, g);
 
-                    draw_text_multiline([0.8, 0.0, 0.0, 1.0], 48, &format!("You lost!\n \nScore: {}\n \n \nPress enter\nto return to\nmain screen.", score),
-                                        Size::from([rect_size * field_size, rect_size * field_size]),
-                                        &mut glyphs, c.transform, g,
+                    let color = if won { theme.win } else { theme.lose };
+                    let view_size = c.get_view_size();
+                    draw_text_multiline(color, scaled(48, screen_scale(view_size)),
+                                        &format!("{}\n \n \nPress enter\nto return to\nmain screen.", result),
+                                        Size::from(view_size),
+                                        glyphs, c.transform, g,
                     ).unwrap();
 
 
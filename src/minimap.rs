@@ -0,0 +1,55 @@
+//! Overview of the whole board in a corner of the window, for boards that scroll.
+
+use piston_window::*;
+use rust_snake::engine::{Game, Point2i};
+use rust_snake::field::Appearance;
+
+use crate::theme::Theme;
+use crate::view::BoardView;
+
+
+/// The minimap takes at most this share of the width and height of the board area.
+const MAX_SHARE: f64 = 0.25;
+/// Distance to the edges of the board area in pixels.
+const MARGIN: f64 = 8.0;
+
+/// Draws the board in the top right corner of the board area with walls, closed doors, food
+/// and the living snakes, and a frame around the part of the board `view` shows.
+pub fn draw_minimap(game: &Game, theme: &Theme, view: &BoardView, c: &Context, g: &mut G2d) {
+    let [left, top, width, height] = view.area;
+    let board = [game.width() as f64, game.height() as f64];
+    let scale = (width * MAX_SHARE / board[0]).min(height * MAX_SHARE / board[1]);
+    let size = [board[0] * scale, board[1] * scale];
+    let origin = [left + width - size[0] - MARGIN, top + MARGIN];
+    let cell = |p: Point2i| [origin[0] + p.x as f64 * scale, origin[1] + p.y as f64 * scale, scale, scale];
+
+    rectangle(theme.overlay, [origin[0], origin[1], size[0], size[1]], c.transform, g);
+
+    for (p, appearance) in game.field.cells() {
+        let color = match appearance {
+            Appearance::Wall => theme.wall,
+            Appearance::Door { open: false, .. } => theme.door,
+            _ => continue,
+        };
+        rectangle(color, cell(p), c.transform, g);
+    }
+
+    for food in &game.food {
+        rectangle(theme.food_color(food.kind), cell(food.position), c.transform, g);
+    }
+
+    for (i, snake) in game.snakes.iter().enumerate().filter(|(_, snake)| snake.is_alive()) {
+        let (body_color, head_color) = theme.player_colors(i);
+        for segment in &snake.body {
+            rectangle(body_color, cell(segment.0), c.transform, g);
+        }
+        rectangle(head_color, cell(snake.head()), c.transform, g);
+    }
+
+    let [x, y, width, height] = view.visible_cells();
+    Rectangle::new_border(theme.highlight, 1.0)
+        .draw([origin[0] + x * scale, origin[1] + y * scale, width * scale, height * scale],
+              &c.draw_state, c.transform, g);
+    Rectangle::new_border(theme.hint, 1.0)
+        .draw([origin[0], origin[1], size[0], size[1]], &c.draw_state, c.transform, g);
+}
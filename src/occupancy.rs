@@ -0,0 +1,219 @@
+//! Which snake and which food covers which cell of the board.
+//!
+//! The grid is updated whenever a snake moves, grows, shrinks or dies and whenever food is placed
+//! or eaten, so looking up a cell takes constant time no matter how long the snakes are. Field
+//! elements keep a grid of their own in `GameField`, together they answer every collision query.
+//!
+//! The grid also counts the free cells food may be placed on, those the level allows food in
+//! which no snake and no food covers, in a Fenwick tree over the cells in row order. Finding the
+//! n-th free cell takes logarithmic time, so placing food doesn't scan the board.
+
+use array2d::Array2D;
+
+use crate::engine::Point2i;
+use crate::food::Food;
+use crate::snake::Snake;
+
+
+#[derive(Copy, Clone)]
+struct Cell {
+    snake: usize,
+    /// Segments on the cell, a snake covers the cell of its head twice while it moves on.
+    segments: u16,
+    food: bool,
+    /// Whether food may be placed on the cell at all.
+    spawnable: bool,
+}
+
+impl Cell {
+    fn is_free(&self) -> bool {
+        self.spawnable && self.segments == 0 && !self.food
+    }
+}
+
+pub struct Occupancy {
+    width: i32,
+    cells: Array2D<Cell>,
+    /// Fenwick tree of the free cells, entry i counts those in the row-major indices `i - (i & -i) + 1 ..= i`.
+    free: Vec<u32>,
+    free_count: usize,
+}
+
+impl Occupancy {
+    /// An empty grid, food may only be placed on cells `spawnable` accepts.
+    pub fn new(width: i32, height: i32, spawnable: impl Fn(Point2i) -> bool) -> Occupancy {
+        let cells = Array2D::filled_by_row_major(
+            || Cell { snake: 0, segments: 0, food: false, spawnable: false },
+            height as usize,
+            width as usize,
+        );
+        let mut occupancy = Occupancy { width, cells, free: Vec::new(), free_count: 0 };
+        for y in 0..height {
+            for x in 0..width {
+                let p = Point2i::new(x, y);
+                occupancy.cells[p.as_coords()].spawnable = spawnable(p);
+            }
+        }
+        occupancy.count_free();
+        occupancy
+    }
+
+    /// Covers the grid with every living snake and all `food`, replacing what it held before.
+    pub(crate) fn fill(&mut self, snakes: &[Snake], food: &[Food]) {
+        let (height, width) = (self.cells.num_rows(), self.cells.num_columns());
+        for row in 0..height {
+            for column in 0..width {
+                let cell = &mut self.cells[(row, column)];
+                cell.segments = 0;
+                cell.food = false;
+            }
+        }
+        for (i, snake) in snakes.iter().enumerate().filter(|(_, s)| s.is_alive()) {
+            for p in snake.cells() {
+                let cell = &mut self.cells[p.as_coords()];
+                cell.snake = i;
+                cell.segments += 1;
+            }
+        }
+        for piece in food {
+            self.cells[piece.position.as_coords()].food = true;
+        }
+        self.count_free();
+    }
+
+    /// The living snake covering `p`.
+    pub fn snake_at(&self, p: Point2i) -> Option<usize> {
+        let cell = self.cells[p.as_coords()];
+        Some(cell.snake).filter(|_| cell.segments > 0)
+    }
+
+    pub fn has_food(&self, p: Point2i) -> bool {
+        self.cells[p.as_coords()].food
+    }
+
+    /// Number of cells food may be placed on.
+    pub fn free_count(&self) -> usize {
+        self.free_count
+    }
+
+    /// The free cell with `index` among all free cells in row order, `index` is below `free_count`.
+    pub fn nth_free(&self, index: usize) -> Point2i {
+        // walks down the tree, skipping every block of cells with no more than `rest` free ones
+        let mut position = 0;
+        let mut rest = index as u32;
+        let mut step = self.free.len().next_power_of_two();
+        while step > 0 {
+            if position + step < self.free.len() && self.free[position + step] <= rest {
+                position += step;
+                rest -= self.free[position];
+            }
+            step /= 2;
+        }
+        self.point(position)
+    }
+
+    /// Every free cell in row order.
+    pub fn free_cells(&self) -> impl Iterator<Item=Point2i> + '_ {
+        self.cells.elements_row_major_iter().enumerate()
+            .filter(|(_, cell)| cell.is_free())
+            .map(move |(i, _)| self.point(i))
+    }
+
+    pub(crate) fn add(&mut self, p: Point2i, snake: usize) {
+        self.update(p, |cell| {
+            cell.snake = snake;
+            cell.segments += 1;
+        });
+    }
+
+    pub(crate) fn remove(&mut self, p: Point2i) {
+        self.update(p, |cell| cell.segments = cell.segments.saturating_sub(1));
+    }
+
+    /// Removes all cells of `snake`, before it dies.
+    pub(crate) fn remove_snake(&mut self, snake: &Snake) {
+        for cell in snake.cells() {
+            self.remove(cell);
+        }
+    }
+
+    pub(crate) fn add_food(&mut self, p: Point2i) {
+        self.update(p, |cell| cell.food = true);
+    }
+
+    pub(crate) fn remove_food(&mut self, p: Point2i) {
+        self.update(p, |cell| cell.food = false);
+    }
+
+    fn point(&self, index: usize) -> Point2i {
+        Point2i::new(index as i32 % self.width, index as i32 / self.width)
+    }
+
+    /// Changes the cell at `p` and keeps the tree up to date if it became free or taken.
+    fn update(&mut self, p: Point2i, change: impl FnOnce(&mut Cell)) {
+        let cell = &mut self.cells[p.as_coords()];
+        let was_free = cell.is_free();
+        change(cell);
+        let is_free = cell.is_free();
+        if was_free == is_free {
+            return;
+        }
+
+        let mut i = (p.y * self.width + p.x) as usize + 1;
+        while i < self.free.len() {
+            if is_free {
+                self.free[i] += 1;
+            } else {
+                self.free[i] -= 1;
+            }
+            i += i & i.wrapping_neg();
+        }
+        if is_free {
+            self.free_count += 1;
+        } else {
+            self.free_count -= 1;
+        }
+    }
+
+    /// Builds the tree from the cells.
+    fn count_free(&mut self) {
+        self.free = vec![0];
+        self.free.extend(self.cells.elements_row_major_iter().map(|cell| cell.is_free() as u32));
+        self.free_count = self.free.iter().sum::<u32>() as usize;
+        for i in 1..self.free.len() {
+            let parent = i + (i & i.wrapping_neg());
+            if parent < self.free.len() {
+                self.free[parent] += self.free[i];
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn free_cells_are_counted_in_row_order() {
+        let mut occupancy = Occupancy::new(7, 5, |p| (p.x + p.y) % 5 != 0);
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        for _ in 0..400 {
+            let p = Point2i::new(rng.gen_range(0..7), rng.gen_range(0..5));
+            match rng.gen_range(0..4) {
+                0 => occupancy.add(p, 0),
+                1 => occupancy.remove(p),
+                2 => occupancy.add_food(p),
+                _ => occupancy.remove_food(p),
+            }
+
+            let free: Vec<Point2i> = occupancy.free_cells().collect();
+            assert_eq!(occupancy.free_count(), free.len());
+            for (i, cell) in free.iter().enumerate() {
+                assert_eq!(occupancy.nth_free(i), *cell);
+            }
+        }
+    }
+}
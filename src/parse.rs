@@ -0,0 +1,65 @@
+//! Helpers shared by the line based text formats: levels, saves and themes.
+//!
+//! Every format reports problems with the 1-based line and column they were found at,
+//! the columns come from `tokens`.
+
+use std::fmt;
+
+
+/// Why a file could not be read, or where its text is malformed.
+#[derive(Debug)]
+pub enum ParseError {
+    Io(std::io::Error),
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Io(err) => write!(f, "{}", err),
+            ParseError::Parse { line, column, message } => {
+                write!(f, "line {}, column {}: {}", line, column, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<std::io::Error> for ParseError {
+    fn from(err: std::io::Error) -> Self {
+        ParseError::Io(err)
+    }
+}
+
+pub fn parse_error<T>(line: usize, column: usize, message: String) -> Result<T, ParseError> {
+    Err(ParseError::Parse { line, column, message })
+}
+
+/// Splits a value into whitespace separated tokens along with their 1-based column.
+/// `offset` is where the value starts in `line`, usually right after the colon of its key.
+pub fn tokens(line: &str, offset: usize) -> Vec<(usize, &str)> {
+    let mut result = Vec::new();
+    let mut start = None;
+
+    for (i, ch) in line[offset..].char_indices() {
+        match (ch.is_whitespace(), start) {
+            (false, None) => start = Some(i),
+            (true, Some(s)) => {
+                result.push((offset + s + 1, &line[offset + s..offset + i]));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+
+    if let Some(s) = start {
+        result.push((offset + s + 1, &line[offset + s..]));
+    }
+
+    result
+}
@@ -0,0 +1,70 @@
+//! Menu drawn over a paused game.
+
+use piston_window::*;
+
+use crate::text_helpers::{draw_text_multiline, scaled, screen_scale};
+use crate::theme::Theme;
+
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PauseChoice {
+    Resume,
+    /// Starts a new game on the same level with the same settings.
+    Restart,
+    Settings,
+    /// Saves the game so it can be resumed from the main menu.
+    Quit,
+}
+
+impl PauseChoice {
+    pub const ALL: [PauseChoice; 4] = [PauseChoice::Resume, PauseChoice::Restart, PauseChoice::Settings, PauseChoice::Quit];
+
+    fn label(self) -> &'static str {
+        match self {
+            PauseChoice::Resume => "Resume",
+            PauseChoice::Restart => "Restart",
+            PauseChoice::Settings => "Settings",
+            PauseChoice::Quit => "Quit to menu",
+        }
+    }
+}
+
+
+#[derive(Default)]
+pub struct PauseMenu {
+    selected: usize,
+}
+
+impl PauseMenu {
+    /// Moves the selection with `Up` and `Down`. Returns the picked entry for `Return`,
+    /// `Escape` and the pause keys resume the game right away.
+    pub fn handle_key(&mut self, key: Key) -> Option<PauseChoice> {
+        let count = PauseChoice::ALL.len();
+        match key {
+            Key::Up => self.selected = (self.selected + count - 1) % count,
+            Key::Down => self.selected = (self.selected + 1) % count,
+            Key::Return => return Some(PauseChoice::ALL[self.selected]),
+            Key::Escape | Key::P | Key::Pause => return Some(PauseChoice::Resume),
+            _ => (),
+        }
+        None
+    }
+
+    pub fn draw(&self, theme: &Theme, glyphs: &mut Glyphs, c: &Context, g: &mut G2d) {
+        let [width, height] = c.get_view_size();
+        let scale = screen_scale([width, height]);
+        rectangle(theme.overlay, [0.0, 0.0, width, height], c.transform, g);
+
+        let top = (height - (100.0 + 40.0 * PauseChoice::ALL.len() as f64) * scale) / 2.0;
+        draw_text_multiline(theme.text, scaled(48, scale), "Paused", Size::from([width, 100.0 * scale]),
+                            glyphs, c.transform.trans(0.0, top), g,
+        ).unwrap();
+
+        for (i, choice) in PauseChoice::ALL.iter().enumerate() {
+            let color = if i == self.selected { theme.highlight } else { theme.hint };
+            draw_text_multiline(color, scaled(28, scale), choice.label(), Size::from([width, 40.0 * scale]),
+                                glyphs, c.transform.trans(0.0, top + (100.0 + 40.0 * i as f64) * scale), g,
+            ).unwrap();
+        }
+    }
+}
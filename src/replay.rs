@@ -0,0 +1,296 @@
+//! Recording of games for later playback.
+//!
+//! A replay stores the seed, every `make_step` call and every direction change
+//! of a game together with its level. Feeding the inputs in the same order into
+//! a game created by `Replay::new_game` reproduces the recorded run exactly.
+//!
+//! File format:
+//!
+//! ```text
+//! seed: 42
+//! step 0.008333333333333333 120
+//! turn 1 left
+//! step 0.008333333333333333 35
+//! level:
+//! <level file, see level.rs>
+//! ```
+//!
+//! `step <dt> [count]` stands for `count` consecutive steps with the same `dt`,
+//! `turn <player> <direction>` for a direction change of a snake (numbered from 1).
+//! A replay holds at most `MAX_INPUTS` inputs.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::engine::{Direction, Game, GameEvent};
+use crate::level::{expect_tokens, Level};
+use crate::parse::{parse_error, tokens, ParseError};
+
+
+/// Most inputs a replay file may hold, over nine hours of steps at 60 frames per second.
+pub const MAX_INPUTS: usize = 2_000_000;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum ReplayInput {
+    Step(f64),
+    Turn(usize, Direction),
+}
+
+impl ReplayInput {
+    pub fn apply(&self, game: &mut Game) -> Vec<GameEvent> {
+        match *self {
+            ReplayInput::Step(dt) => game.make_step(dt),
+            ReplayInput::Turn(player, dir) => {
+                game.change_dir(player, dir);
+                Vec::new()
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Replay {
+    pub seed: u64,
+    pub level: Level,
+    pub inputs: Vec<ReplayInput>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, level: Level) -> Replay {
+        Replay {
+            seed,
+            level,
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Creates a game in the state the recording started from.
+    pub fn new_game(&self) -> Game {
+        Game::with_seed(&self.level, self.seed)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        self.level.check_writable()?;
+        fs::write(path, self.to_string())
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Replay, ParseError> {
+        Replay::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(source: &str) -> Result<Replay, ParseError> {
+        let mut seed = None;
+        let mut inputs = Vec::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let line_no = index + 1;
+            let parts = tokens(line, 0);
+            // where a missing value would have been
+            let end = line.trim_end().len() + 2;
+
+            match parts.first().map(|part| part.1) {
+                None => (),
+                Some("seed:") => {
+                    expect_tokens(line_no, end, "seed", &parts[1..], 1)?;
+                    match parts[1].1.parse() {
+                        Ok(s) => seed = Some(s),
+                        Err(_) => return parse_error(line_no, parts[1].0, format!("expected a seed number, found '{}'", parts[1].1)),
+                    }
+                }
+                Some("step") => {
+                    let (dt, count) = match parts[1..] {
+                        [dt] => (dt, None),
+                        [dt, count] => (dt, Some(count)),
+                        _ => return parse_error(line_no, parts.get(3).map_or(end, |p| p.0), "expected 'step <dt> [count]'".to_string()),
+                    };
+                    let dt = match dt.1.parse::<f64>() {
+                        Ok(dt) if dt.is_finite() && dt >= 0.0 => dt,
+                        _ => return parse_error(line_no, dt.0, format!("expected a duration in seconds, found '{}'", dt.1)),
+                    };
+                    let count = match count.map(|(column, c)| (column, c.parse::<usize>())) {
+                        None => 1,
+                        Some((_, Ok(count))) if count <= MAX_INPUTS - inputs.len() => count,
+                        Some((column, Ok(_))) => {
+                            return parse_error(line_no, column, format!("a replay holds at most {} inputs", MAX_INPUTS));
+                        }
+                        Some((column, Err(_))) => return parse_error(line_no, column, "expected a number of steps".to_string()),
+                    };
+                    inputs.extend(std::iter::repeat_n(ReplayInput::Step(dt), count));
+                }
+                Some("turn") => {
+                    let (player, dir) = match parts[1..] {
+                        [player, dir] => (player, dir),
+                        _ => return parse_error(line_no, parts.get(3).map_or(end, |p| p.0),
+                                                "expected 'turn <player> <up|down|left|right>'".to_string()),
+                    };
+                    let player = match player.1.parse::<usize>() {
+                        Ok(player) if player > 0 => player,
+                        _ => return parse_error(line_no, player.0, format!("expected a player number, found '{}'", player.1)),
+                    };
+                    let dir = match Direction::from_name(dir.1) {
+                        Some(dir) => dir,
+                        None => return parse_error(line_no, dir.0, format!("unknown direction '{}'", dir.1)),
+                    };
+                    if inputs.len() == MAX_INPUTS {
+                        return parse_error(line_no, 1, format!("a replay holds at most {} inputs", MAX_INPUTS));
+                    }
+                    inputs.push(ReplayInput::Turn(player - 1, dir));
+                }
+                Some("level:") => {
+                    let seed = match seed {
+                        Some(seed) => seed,
+                        None => return parse_error(1, 1, "missing 'seed: <number>'".to_string()),
+                    };
+
+                    let level_source: Vec<&str> = source.lines().skip(line_no).collect();
+                    let level = Level::parse(&level_source.join("\n")).map_err(|err| match err {
+                        ParseError::Parse { line, column, message } => {
+                            ParseError::Parse { line: line + line_no, column, message }
+                        }
+                        err => err,
+                    })?;
+
+                    return Ok(Replay { seed, level, inputs });
+                }
+                Some(other) => return parse_error(line_no, parts[0].0, format!("unknown replay entry '{}'", other)),
+            }
+        }
+
+        parse_error(source.lines().count() + 1, 1, "missing 'level:' section".to_string())
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "seed: {}", self.seed)?;
+
+        let mut i = 0;
+        while i < self.inputs.len() {
+            match self.inputs[i] {
+                ReplayInput::Step(dt) => {
+                    let count = self.inputs[i..].iter().take_while(|input| **input == ReplayInput::Step(dt)).count();
+                    writeln!(f, "step {} {}", dt, count)?;
+                    i += count;
+                }
+                ReplayInput::Turn(player, dir) => {
+                    writeln!(f, "turn {} {}", player + 1, dir.name())?;
+                    i += 1;
+                }
+            }
+        }
+
+        writeln!(f, "level:")?;
+        write!(f, "{}", self.level)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{Autopilot, Strategy};
+
+    const TURNS: [Direction; 4] = [Direction::Up, Direction::Left, Direction::Down, Direction::Right];
+
+    /// Plays a seeded game of the classic level with two computer players and a few turns of
+    /// its own, returns the recording and the events and saved state after every `make_step`.
+    fn record(seed: u64, steps: usize) -> (Replay, Vec<(Vec<GameEvent>, String)>) {
+        let level = Level::classic(20, 20);
+        let mut game = Game::with_seed(&level, seed);
+        game.start_recording();
+        let mut autopilots = [Autopilot::new(0, Strategy::Greedy), Autopilot::new(1, Strategy::Survival)];
+
+        let mut states = Vec::new();
+        for i in 0..steps {
+            if game.is_over() {
+                break;
+            }
+            for autopilot in &mut autopilots {
+                autopilot.update(&mut game);
+            }
+            if i % 23 == 0 {
+                game.change_dir(0, TURNS[i / 23 % TURNS.len()]);
+            }
+            // frames shorter than a grid step, so most calls don't move the snakes
+            let dt = game.step_time() as f64 * if i % 3 == 0 { 0.7 } else { 0.4 };
+            let events = game.make_step(dt);
+            states.push((events, game.save_state().to_string()));
+        }
+        (game.take_recording().unwrap(), states)
+    }
+
+    #[test]
+    fn replays_reproduce_the_game_step_for_step() {
+        let (replay, states) = record(13, 1500);
+        assert!(replay.inputs.iter().any(|input| matches!(input, ReplayInput::Turn(1, _))));
+        assert!(states.iter().any(|(events, _)| events.iter().any(|e| matches!(e, GameEvent::Eat { player: 1, .. }))));
+
+        let mut game = replay.new_game();
+        let mut expected = states.iter();
+        for input in &replay.inputs {
+            let events = input.apply(&mut game);
+            if let ReplayInput::Step(_) = input {
+                let (recorded_events, recorded_state) = expected.next().expect("more steps than recorded");
+                assert_eq!(&events, recorded_events);
+                assert_eq!(&game.save_state().to_string(), recorded_state, "after step {}", game.step_count());
+            }
+        }
+        assert!(expected.next().is_none());
+    }
+
+    #[test]
+    fn text_format_round_trips() {
+        let (replay, states) = record(5, 600);
+        let written = replay.to_string();
+        let parsed = Replay::parse(&written).unwrap_or_else(|err| panic!("{}\n{}", err, written));
+
+        assert_eq!(parsed.seed, replay.seed);
+        assert!(parsed.inputs == replay.inputs);
+        assert_eq!(parsed.to_string(), written);
+
+        let mut game = parsed.new_game();
+        for input in &parsed.inputs {
+            input.apply(&mut game);
+        }
+        assert_eq!(game.save_state().to_string(), states.last().unwrap().1);
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        let error = |source: &str| match Replay::parse(source) {
+            Err(ParseError::Parse { line, column, .. }) => (line, column),
+            _ => panic!("expected a parse error"),
+        };
+        assert_eq!(error("seed: 1\nstep fast\nlevel:\n"), (2, 6));
+        assert_eq!(error("seed: 1\nturn 0 left\nlevel:\n"), (2, 6));
+        assert_eq!(error("seed: 1\njump\nlevel:\n"), (2, 1));
+        assert_eq!(error("step 0.1 2\nlevel:\n"), (1, 1));
+        assert_eq!(error("seed: 1\nlevel:\nsnake: right 1\n---\n@.?\n"), (5, 3));
+
+        // columns come from the tokens, wherever they are on the line
+        assert_eq!(error("seed: 1\n  step   0.1 lots\nlevel:\n"), (2, 14));
+        assert_eq!(error("seed: 1\nturn 1   sideways\nlevel:\n"), (2, 10));
+        assert_eq!(error("seed: x\nlevel:\n"), (1, 7));
+        assert_eq!(error("seed:\nlevel:\n"), (1, 7));
+        assert_eq!(error("seed: 1\nturn 1\nlevel:\n"), (2, 8));
+    }
+
+    #[test]
+    fn parse_rejects_bad_durations_and_counts() {
+        let error = |line: &str| match Replay::parse(&format!("seed: 1\n{}\nlevel:\n", line)) {
+            Err(ParseError::Parse { line, column, .. }) => (line, column),
+            _ => panic!("expected a parse error for '{}'", line),
+        };
+        for dt in ["NaN", "inf", "-inf", "-0.5", "1e400"] {
+            assert_eq!(error(&format!("step {} 3", dt)), (2, 6));
+        }
+        assert_eq!(error(&format!("step 0.1 {}", usize::MAX)), (2, 10));
+        assert_eq!(error(&format!("step 0.1 {}", MAX_INPUTS + 1)), (2, 10));
+
+        // the limit counts the inputs of all lines
+        let half = MAX_INPUTS / 2 + 1;
+        let source = format!("seed: 1\nstep 0.1 {}\nstep 0.2 {}\nlevel:\n", half, half);
+        assert!(matches!(Replay::parse(&source), Err(ParseError::Parse { line: 3, column: 10, .. })));
+    }
+}
@@ -0,0 +1,92 @@
+use piston_window::*;
+
+use crate::game::MainGame;
+use rust_snake::replay::{Replay, ReplayInput};
+use crate::sound::Sound;
+use crate::theme::Skin;
+
+
+pub struct ReplayViewer {
+    main_game: MainGame,
+    inputs: Vec<ReplayInput>,
+    position: usize,
+    paused: bool,
+    speed: f64,
+    pending_time: f64,
+}
+
+impl ReplayViewer {
+    pub fn new(replay: Replay) -> ReplayViewer {
+        ReplayViewer {
+            main_game: MainGame::from_game(replay.new_game()),
+            inputs: replay.inputs,
+            position: 0,
+            paused: false,
+            speed: 1.0,
+            pending_time: 0.0,
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.position >= self.inputs.len()
+    }
+
+    fn apply_next(&mut self, sound: &Sound) {
+        let events = self.inputs[self.position].apply(self.main_game.game_mut());
+        self.position += 1;
+
+        sound.play_for_events(&events);
+        if self.main_game.is_over() {
+            // the recorded run ends here
+            self.position = self.inputs.len();
+        }
+    }
+
+    /// Plays back recorded inputs until the snake has moved by one cell.
+    fn step_frame(&mut self, sound: &Sound) {
+        let steps = self.main_game.game().step_count();
+        while !self.finished() && self.main_game.game().step_count() == steps {
+            self.apply_next(sound);
+        }
+    }
+
+    pub fn run(&mut self, window: &mut PistonWindow, skin: &mut Skin, sound: &Sound, e: Event) {
+        if let Some(Button::Keyboard(key)) = e.press_args() {
+            match key {
+                Key::Space => self.paused = !self.paused,
+                Key::Up => self.speed = (self.speed * 2.0).min(16.0),
+                Key::Down => self.speed = (self.speed / 2.0).max(0.25),
+                Key::Right if self.paused => self.step_frame(sound),
+                _ => (),
+            }
+        }
+
+        if let Some(args) = e.update_args() {
+            self.main_game.update_camera(args.dt);
+            if !self.paused {
+                self.pending_time += args.dt * self.speed;
+
+                while let Some(&input) = self.inputs.get(self.position) {
+                    if let ReplayInput::Step(dt) = input {
+                        if dt > self.pending_time {
+                            break;
+                        }
+                        self.pending_time -= dt;
+                    }
+                    self.apply_next(sound);
+                }
+            }
+        }
+
+        let status = if self.finished() {
+            "replay finished, press escape".to_string()
+        } else if self.paused {
+            format!("replay {}x, paused (right: next step)", self.speed)
+        } else {
+            format!("replay {}x (space: pause, up/down: speed)", self.speed)
+        };
+        self.main_game.set_status(Some(status));
+
+        self.main_game.render(window, skin, &e);
+    }
+}
@@ -0,0 +1,502 @@
+//! Games saved in the middle of a run.
+//!
+//! Unlike a replay, a save stores the state of a game at a single moment: the snakes,
+//! the food, the field elements and the position of the random number generator.
+//! A game continued with `Game::from_save` plays on exactly like the original would have.
+//!
+//! File format:
+//!
+//! ```text
+//! seed: 42
+//! rng: 96
+//! step: 120
+//! progress: 0.35
+//! step time: 0.3
+//! time: 41.25
+//! food: 4 7 normal
+//! food: 12 3 golden expires 131
+//! effect: speed-up 150
+//! snake 1: 5 9 down 6 3
+//! body 1: 5,3,down 5,4,down 5,5,down 5,6,down 5,7,down 5,8,down
+//! buffer 1: left
+//! snake 2: 15 2 up 5 0 died 87
+//! body 2: 15,6,up 15,5,up 15,4,up 15,3,up
+//! cpu 2: greedy
+//! element 2: 1 3
+//! level:
+//! <level file, see level.rs>
+//! ```
+//!
+//! * `time: <seconds>` time played so far, 0 if missing
+//! * `food: <x> <y> <kind> [expires <step>]` one line for every piece of food, see food.rs for the kinds
+//! * `effect: <kind> <step>` effect of eaten food which ends in grid step `step`
+//! * `snake <number>: <x> <y> <direction> <length> <score> [died <step>]` head of a snake, numbered from 1
+//! * `body <number>: <x>,<y>,<direction> ...` the other segments from the tail on, with the direction they were left in
+//! * `buffer <number>: <direction> ...` turns which were pressed but not applied yet
+//! * `cpu <number>: <strategy>` the snake is steered by the computer, see ai.rs for the strategies
+//! * `element <number>: <value> ...` state of a field element, numbered in the order they were added
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::ai::Strategy;
+use crate::engine::{Direction, Point2i};
+use crate::food::{Effect, Food, FoodKind};
+use crate::level::{expect_tokens, parse_number, Level};
+use crate::parse::{parse_error, tokens, ParseError};
+
+
+#[derive(Clone)]
+pub struct SnakeState {
+    pub body: Vec<(Point2i, Direction)>,
+    pub head: Point2i,
+    pub dir: Direction,
+    pub length: usize,
+    pub score: usize,
+    pub buffer: Vec<Direction>,
+    pub died_at: Option<usize>,
+}
+
+#[derive(Clone)]
+pub struct SaveGame {
+    /// The level the game was created from, with the border rule in effect.
+    pub level: Level,
+    pub seed: u64,
+    pub rng_position: u128,
+    pub step_count: usize,
+    pub progress: f32,
+    pub step_time: f32,
+    /// Seconds played so far.
+    pub play_time: f64,
+    pub food: Vec<Food>,
+    pub effects: Vec<Effect>,
+    pub snakes: Vec<SnakeState>,
+    /// State of every field element, empty for elements without one.
+    pub elements: Vec<Vec<usize>>,
+    /// Snakes steered by the computer, by player index, with their strategy.
+    pub autopilots: Vec<(usize, Strategy)>,
+}
+
+fn parse_value<T: std::str::FromStr>(line: usize, (column, token): (usize, &str), what: &str) -> Result<T, ParseError> {
+    match token.parse() {
+        Ok(value) => Ok(value),
+        Err(_) => parse_error(line, column, format!("expected {}, found '{}'", what, token)),
+    }
+}
+
+fn parse_dir(line: usize, (column, token): (usize, &str)) -> Result<Direction, ParseError> {
+    match Direction::from_name(token) {
+        Some(dir) => Ok(dir),
+        None => parse_error(line, column, format!("unknown direction '{}'", token)),
+    }
+}
+
+/// Parses a `<x>,<y>,<direction>` body segment.
+fn parse_segment(line: usize, (column, token): (usize, &str)) -> Result<(Point2i, Direction), ParseError> {
+    let parts: Vec<&str> = token.split(',').collect();
+    if let [x, y, dir] = parts[..] {
+        if let (Ok(x), Ok(y), Some(dir)) = (x.parse(), y.parse(), Direction::from_name(dir)) {
+            return Ok((Point2i::new(x, y), dir));
+        }
+    }
+    parse_error(line, column, format!("expected '<x>,<y>,<direction>', found '{}'", token))
+}
+
+impl SaveGame {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        self.level.check_writable()?;
+        fs::write(path, self.to_string())
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<SaveGame, ParseError> {
+        SaveGame::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(source: &str) -> Result<SaveGame, ParseError> {
+        let mut seed = None;
+        let mut rng_position = None;
+        let mut step_count = None;
+        let mut progress = None;
+        let mut step_time = None;
+        let mut play_time = 0.0;
+        let mut food = Vec::new();
+        let mut effects = Vec::new();
+        let mut snakes: Vec<SnakeState> = Vec::new();
+        // element states with their number, line and column, checked against the level at the end
+        let mut element_states = Vec::new();
+        // the line and column of every snake, for errors about the number of snakes
+        let mut snake_lines = Vec::new();
+        let mut autopilots = Vec::new();
+        // every position with its line and column, checked against the level size at the end
+        let mut positions = Vec::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let line_no = index + 1;
+            let key_column = line.len() - line.trim_start().len() + 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let colon = match line.find(':') {
+                Some(c) => c,
+                None => return parse_error(line_no, key_column, "expected 'key: value'".to_string()),
+            };
+            let key = line[..colon].trim();
+            let values = tokens(line, colon + 1);
+
+            match key {
+                "seed" => {
+                    expect_tokens(line_no, colon + 2, key, &values, 1)?;
+                    seed = Some(parse_value(line_no, values[0], "a seed number")?);
+                }
+                "rng" => {
+                    expect_tokens(line_no, colon + 2, key, &values, 1)?;
+                    rng_position = Some(parse_value(line_no, values[0], "a number")?);
+                }
+                "step" => {
+                    expect_tokens(line_no, colon + 2, key, &values, 1)?;
+                    step_count = Some(parse_number(line_no, values[0])?);
+                }
+                "progress" => {
+                    expect_tokens(line_no, colon + 2, key, &values, 1)?;
+                    progress = Some(parse_value(line_no, values[0], "a number")?);
+                }
+                "step time" => {
+                    expect_tokens(line_no, colon + 2, key, &values, 1)?;
+                    step_time = Some(parse_value(line_no, values[0], "a number")?);
+                }
+                "time" => {
+                    expect_tokens(line_no, colon + 2, key, &values, 1)?;
+                    play_time = parse_value(line_no, values[0], "a number of seconds")?;
+                }
+                "food" => {
+                    let expires_at = match values.len() {
+                        3 => None,
+                        5 if values[3].1 == "expires" => Some(parse_number(line_no, values[4])?),
+                        _ => return parse_error(line_no, colon + 2,
+                                                "expected '<x> <y> <kind> [expires <step>]'".to_string()),
+                    };
+                    let position = Point2i::new(parse_value(line_no, values[0], "a number")?,
+                                                parse_value(line_no, values[1], "a number")?);
+                    let kind = match FoodKind::from_name(values[2].1) {
+                        Some(kind) => kind,
+                        None => return parse_error(line_no, values[2].0, format!("unknown food kind '{}'", values[2].1)),
+                    };
+                    positions.push((position, line_no, values[0].0));
+                    food.push(Food { kind, position, expires_at });
+                }
+                "effect" => {
+                    expect_tokens(line_no, colon + 2, key, &values, 2)?;
+                    let kind = match FoodKind::from_name(values[0].1) {
+                        Some(kind) if kind.effect_steps().is_some() => kind,
+                        _ => return parse_error(line_no, values[0].0, format!("'{}' is no food with an effect", values[0].1)),
+                    };
+                    effects.push(Effect { kind, ends_at: parse_number(line_no, values[1])? });
+                }
+                "level" => {
+                    let level_source: Vec<&str> = source.lines().skip(line_no).collect();
+                    let level = Level::parse(&level_source.join("\n")).map_err(|err| match err {
+                        ParseError::Parse { line, column, message } => {
+                            ParseError::Parse { line: line + line_no, column, message }
+                        }
+                        err => err,
+                    })?;
+
+                    for (p, line, column) in positions {
+                        if !(0..level.width).contains(&p.x) || !(0..level.height).contains(&p.y) {
+                            return parse_error(line, column, "position outside of the level".to_string());
+                        }
+                    }
+
+                    if snakes.len() != level.snakes.len() {
+                        let (line, column) = snake_lines.get(level.snakes.len()).copied().unwrap_or((line_no, 1));
+                        return parse_error(line, column, format!("expected as many snakes as the level has ({}), found {}", level.snakes.len(), snakes.len()));
+                    }
+
+                    let mut elements = vec![Vec::new(); level.element_count()];
+                    for (number, state, line, column) in element_states {
+                        match elements.get_mut(number - 1) {
+                            Some(element) => *element = state,
+                            None => return parse_error(line, column, format!("expected an element number up to {}", level.element_count())),
+                        }
+                    }
+
+                    let missing = |key: &str| parse_error(1, 1, format!("missing '{}'", key));
+                    return Ok(SaveGame {
+                        level,
+                        seed: match seed { Some(s) => s, None => return missing("seed") },
+                        rng_position: match rng_position { Some(r) => r, None => return missing("rng") },
+                        step_count: match step_count { Some(s) => s, None => return missing("step") },
+                        progress: match progress { Some(p) => p, None => return missing("progress") },
+                        step_time: match step_time { Some(t) => t, None => return missing("step time") },
+                        play_time,
+                        food,
+                        effects,
+                        snakes,
+                        elements,
+                        autopilots,
+                    });
+                }
+                _ => {
+                    // `snake`, `body`, `buffer`, `cpu` and `element` are followed by a number
+                    let number = key.split_once(' ').and_then(|(name, n)| {
+                        n.trim().parse::<usize>().ok().filter(|n| *n > 0).map(|n| (name, n))
+                    });
+
+                    match number {
+                        Some(("snake", number)) => {
+                            if number != snakes.len() + 1 {
+                                return parse_error(line_no, key_column, format!("expected 'snake {}'", snakes.len() + 1));
+                            }
+                            let died_at = match values.len() {
+                                5 => None,
+                                7 if values[5].1 == "died" => Some(parse_number(line_no, values[6])?),
+                                _ => return parse_error(line_no, colon + 2,
+                                                        "expected '<x> <y> <direction> <length> <score> [died <step>]'".to_string()),
+                            };
+                            let head = Point2i::new(parse_value(line_no, values[0], "a number")?,
+                                                    parse_value(line_no, values[1], "a number")?);
+                            positions.push((head, line_no, values[0].0));
+                            snake_lines.push((line_no, key_column));
+                            snakes.push(SnakeState {
+                                body: Vec::new(),
+                                head,
+                                dir: parse_dir(line_no, values[2])?,
+                                length: parse_number(line_no, values[3])?,
+                                score: parse_number(line_no, values[4])?,
+                                buffer: Vec::new(),
+                                died_at,
+                            });
+                        }
+                        Some(("body", number)) | Some(("buffer", number)) | Some(("cpu", number)) if number > snakes.len() => {
+                            return parse_error(line_no, key_column, format!("'{}' before 'snake {}'", key, number));
+                        }
+                        Some(("body", number)) => {
+                            for value in values {
+                                let segment = parse_segment(line_no, value)?;
+                                positions.push((segment.0, line_no, value.0));
+                                snakes[number - 1].body.push(segment);
+                            }
+                        }
+                        Some(("buffer", number)) => {
+                            for value in values {
+                                snakes[number - 1].buffer.push(parse_dir(line_no, value)?);
+                            }
+                        }
+                        Some(("cpu", number)) => {
+                            expect_tokens(line_no, colon + 2, key, &values, 1)?;
+                            match Strategy::from_name(values[0].1) {
+                                Some(strategy) => autopilots.push((number - 1, strategy)),
+                                None => return parse_error(line_no, values[0].0, format!("unknown strategy '{}'", values[0].1)),
+                            }
+                        }
+                        Some(("element", number)) => {
+                            let state = values.into_iter()
+                                .map(|value| parse_number(line_no, value))
+                                .collect::<Result<_, _>>()?;
+                            element_states.push((number, state, line_no, key_column));
+                        }
+                        _ => return parse_error(line_no, key_column, format!("unknown save entry '{}'", key)),
+                    }
+                }
+            }
+        }
+
+        parse_error(source.lines().count() + 1, 1, "missing 'level:' section".to_string())
+    }
+}
+
+impl fmt::Display for SaveGame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "seed: {}", self.seed)?;
+        writeln!(f, "rng: {}", self.rng_position)?;
+        writeln!(f, "step: {}", self.step_count)?;
+        writeln!(f, "progress: {}", self.progress)?;
+        writeln!(f, "step time: {}", self.step_time)?;
+        writeln!(f, "time: {}", self.play_time)?;
+        for food in &self.food {
+            write!(f, "food: {} {} {}", food.position.x, food.position.y, food.kind.name())?;
+            match food.expires_at {
+                Some(step) => writeln!(f, " expires {}", step)?,
+                None => writeln!(f)?,
+            }
+        }
+        for effect in &self.effects {
+            writeln!(f, "effect: {} {}", effect.kind.name(), effect.ends_at)?;
+        }
+
+        for (i, snake) in self.snakes.iter().enumerate() {
+            write!(f, "snake {}: {} {} {} {} {}", i + 1, snake.head.x, snake.head.y, snake.dir.name(), snake.length, snake.score)?;
+            match snake.died_at {
+                Some(step) => writeln!(f, " died {}", step)?,
+                None => writeln!(f)?,
+            }
+
+            if !snake.body.is_empty() {
+                let body: Vec<String> = snake.body.iter().map(|(p, dir)| format!("{},{},{}", p.x, p.y, dir.name())).collect();
+                writeln!(f, "body {}: {}", i + 1, body.join(" "))?;
+            }
+
+            if !snake.buffer.is_empty() {
+                let buffer: Vec<&str> = snake.buffer.iter().map(|dir| dir.name()).collect();
+                writeln!(f, "buffer {}: {}", i + 1, buffer.join(" "))?;
+            }
+
+            for (_, strategy) in self.autopilots.iter().filter(|(player, _)| *player == i) {
+                writeln!(f, "cpu {}: {}", i + 1, strategy.name())?;
+            }
+        }
+
+        for (i, state) in self.elements.iter().enumerate().filter(|(_, state)| !state.is_empty()) {
+            let values: Vec<String> = state.iter().map(|v| v.to_string()).collect();
+            writeln!(f, "element {}: {}", i + 1, values.join(" "))?;
+        }
+
+        writeln!(f, "level:")?;
+        write!(f, "{}", self.level)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::Autopilot;
+    use crate::engine::{Game, GameEvent};
+
+    /// A small board with a timed door and a teleporter with a cooldown, so the snake uses both often.
+    const LEVEL: &str = "\
+snake: right 3
+door: A timer 5 4
+teleport: a b cooldown 6
+---
+..........
+.a........
+....A.....
+....A...b.
+.@........
+..........
+..........
+";
+
+    /// Plays `frames` frames steered by a fresh autopilot, returns the events and saved state after each.
+    fn play(game: &mut Game, frames: usize) -> Vec<(Vec<GameEvent>, String)> {
+        let mut autopilot = Autopilot::new(0, Strategy::Greedy);
+        let mut states = Vec::new();
+        for i in 0..frames {
+            autopilot.update(game);
+            let dt = game.step_time() as f64 * if i % 2 == 0 { 0.6 } else { 0.9 };
+            let events = game.make_step(dt);
+            states.push((events, game.save_state().to_string()));
+        }
+        states
+    }
+
+    #[test]
+    fn loaded_games_continue_like_the_original() {
+        let level = Level::parse(LEVEL).unwrap_or_else(|err| panic!("{}", err));
+        let mut game = Game::with_seed(&level, 3);
+        let start = game.save_state().rng_position;
+
+        let (mut rng_moved, mut food_expiring, mut cooling, mut door_running) = (false, false, false, false);
+        while !game.is_over() && game.step_count() < 2000 {
+            let save = game.save_state();
+            rng_moved |= save.rng_position != start;
+            food_expiring |= save.food.iter().any(|food| food.expires_at.is_some());
+            // teleporters store `[next exit, cooling, random]`, doors `[open, wanted, counter, occupied]`
+            cooling |= save.elements.iter().any(|state| state.len() == 3 && state[1] > 0);
+            door_running |= save.elements.iter().any(|state| state.len() == 4 && state[2] > 0);
+
+            let written = save.to_string();
+            let parsed = SaveGame::parse(&written).unwrap_or_else(|err| panic!("{}\n{}", err, written));
+            assert_eq!(parsed.to_string(), written);
+
+            let mut loaded = Game::from_save(&parsed);
+            assert_eq!(play(&mut loaded, 40), play(&mut game, 40), "continuing from\n{}", written);
+        }
+
+        assert!(rng_moved && food_expiring && cooling && door_running,
+                "not every kind of state was saved: rng {}, food {}, teleporter {}, door {}",
+                rng_moved, food_expiring, cooling, door_running);
+    }
+
+    #[test]
+    fn autopilots_are_saved() {
+        let mut save = Game::with_seed(&Level::classic(20, 20), 1).save_state();
+        save.autopilots = vec![(1, Strategy::Hamiltonian)];
+        let written = save.to_string();
+        assert!(written.contains("cpu 2: hamiltonian\n"));
+        assert_eq!(SaveGame::parse(&written).unwrap().autopilots, save.autopilots);
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        let error = |source: &str| match SaveGame::parse(source) {
+            Err(ParseError::Parse { line, column, .. }) => (line, column),
+            _ => panic!("expected a parse error"),
+        };
+        assert_eq!(error("seed: x\nlevel:\n"), (1, 7));
+        assert_eq!(error("seed: 1\nsnake 2: 1 1 up 3 0\nlevel:\n"), (2, 1));
+        assert_eq!(error("seed: 1\nsnake 1: 1 1 up 3 0\ncpu 1: clever\nlevel:\n"), (3, 8));
+        assert_eq!(error("seed: 1\ncpu 1: greedy\nlevel:\n"), (2, 1));
+        assert_eq!(error("seed: 1\nsnake 1: 1 1 up 3 0\nbody 1: 1,1\nlevel:\n"), (3, 9));
+        assert_eq!(error("seed: 1\neffect: bonus 30\nlevel:\n"), (2, 9));
+    }
+
+    #[test]
+    fn effects_are_saved() {
+        let mut save = Game::with_seed(&Level::classic(20, 20), 1).save_state();
+        save.effects = vec![Effect { kind: FoodKind::SlowDown, ends_at: 70 }];
+        let written = save.to_string();
+        assert!(written.contains("effect: slow-down 70\n"));
+
+        let loaded = Game::from_save(&SaveGame::parse(&written).unwrap());
+        assert_eq!(loaded.effects(), &save.effects[..]);
+        assert_eq!(loaded.step_time(), save.step_time * FoodKind::SlowDown.step_time_factor());
+    }
+
+    /// A save of a single snake on a level with one door, with `entries` added before the level.
+    fn save_with(entries: &str, level: &str) -> String {
+        format!("seed: 1\nrng: 0\nstep: 0\nprogress: 0\nstep time: 0.2\n{}level:\n{}", entries, level)
+    }
+
+    const ONE_SNAKE: &str = "snake: right 1\ndoor: A 3\n---\n@.A\n...\n";
+    const TWO_SNAKES: &str = "snake: right 1\n---\n@..\n..2\n";
+
+    fn error(source: &str) -> (usize, usize, String) {
+        match SaveGame::parse(source) {
+            Err(ParseError::Parse { line, column, message }) => (line, column, message),
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("parsed without an error"),
+        }
+    }
+
+    #[test]
+    fn element_numbers_are_limited_by_the_level() {
+        // the walls and the door
+        assert!(SaveGame::parse(&save_with("snake 1: 0 0 right 1 0\nelement 2: 0 0 1 0\n", ONE_SNAKE)).is_ok());
+
+        let (line, column, message) = error(&save_with("snake 1: 0 0 right 1 0\nelement 3: 1\n", ONE_SNAKE));
+        assert_eq!((line, column), (7, 1));
+        assert_eq!(message, "expected an element number up to 2");
+
+        let huge = format!("snake 1: 0 0 right 1 0\nelement {}: 1\n", usize::MAX);
+        let (line, column, _) = error(&save_with(&huge, ONE_SNAKE));
+        assert_eq!((line, column), (7, 1));
+    }
+
+    #[test]
+    fn snake_count_has_to_match_the_level() {
+        let (line, column, message) = error(&save_with("", ONE_SNAKE));
+        assert_eq!((line, column), (6, 1));
+        assert_eq!(message, "expected as many snakes as the level has (1), found 0");
+
+        let one = "snake 1: 0 0 right 1 0\n";
+        assert_eq!(error(&save_with(one, TWO_SNAKES)).0, 7);
+
+        let three = "snake 1: 0 0 right 1 0\nsnake 2: 2 1 right 1 0\n  snake 3: 1 1 right 1 0\n";
+        let (line, column, _) = error(&save_with(three, TWO_SNAKES));
+        assert_eq!((line, column), (8, 3));
+    }
+}
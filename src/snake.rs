@@ -0,0 +1,238 @@
+use std::collections::VecDeque;
+
+use crate::engine::{Direction, Point2f, Point2i};
+use crate::food::FoodKind;
+use crate::save::SnakeState;
+
+
+struct DirBuffer {
+    data: VecDeque<Direction>,
+}
+
+impl DirBuffer {
+    pub fn new() -> DirBuffer {
+        DirBuffer {
+            data: VecDeque::new(),
+        }
+    }
+
+    pub fn enqueue_dir(&mut self, dir: Direction) {
+        if !self.data.contains(&dir) {
+            self.data.push_back(dir);
+        }
+    }
+
+    pub fn next_dir(&mut self) -> Option<Direction> {
+        self.data.pop_front()
+    }
+
+    pub fn dirs(&self) -> Vec<Direction> {
+        self.data.iter().copied().collect()
+    }
+}
+
+
+pub struct Snake {
+    /// Segments from tail to neck, each with the direction the snake moved when leaving it.
+    pub body: VecDeque<(Point2i, Point2i)>,
+    front: Point2i,
+    dir: Direction,
+    length: usize,
+    score: usize,
+    dir_buffer: DirBuffer,
+    died_at: Option<usize>,
+}
+
+impl Snake {
+    pub fn new(start: Point2i, dir: Direction, length: usize) -> Snake {
+        Snake {
+            body: VecDeque::new(),
+            front: start,
+            dir,
+            length,
+            score: 0,
+            dir_buffer: DirBuffer::new(),
+            died_at: None,
+        }
+    }
+
+    pub(crate) fn from_state(state: &SnakeState) -> Snake {
+        let mut dir_buffer = DirBuffer::new();
+        for dir in &state.buffer {
+            dir_buffer.enqueue_dir(*dir);
+        }
+
+        Snake {
+            body: state.body.iter().map(|(p, dir)| (*p, dir.vector())).collect(),
+            front: state.head,
+            dir: state.dir,
+            length: state.length,
+            score: state.score,
+            dir_buffer,
+            died_at: state.died_at,
+        }
+    }
+
+    pub(crate) fn state(&self) -> SnakeState {
+        SnakeState {
+            body: self.body.iter()
+                .map(|(p, v)| (*p, Direction::from_vector(*v).unwrap_or(self.dir)))
+                .collect(),
+            head: self.front,
+            dir: self.dir,
+            length: self.length,
+            score: self.score,
+            buffer: self.dir_buffer.dirs(),
+            died_at: self.died_at,
+        }
+    }
+
+    pub fn head(&self) -> Point2i {
+        self.front
+    }
+
+    pub fn dir(&self) -> Direction {
+        self.dir
+    }
+
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Points for the food eaten so far.
+    pub fn score(&self) -> usize {
+        self.score
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.died_at.is_none()
+    }
+
+    /// The grid step in which the snake died.
+    pub fn died_at(&self) -> Option<usize> {
+        self.died_at
+    }
+
+    /// Every cell covered by the snake, from the tail to the head.
+    pub fn cells(&self) -> impl Iterator<Item=Point2i> + '_ {
+        self.body.iter().map(|segment| segment.0).chain(std::iter::once(self.front))
+    }
+
+    pub fn interpolated_head(&self, progress: f32) -> Point2f {
+        if !self.is_alive() {
+            return Point2f::new(self.front.x as f32, self.front.y as f32);
+        }
+
+        let dir_x = self.dir.vector().x as f32;
+        let dir_y = self.dir.vector().y as f32;
+        // self.front represents the next position on grid
+        // when progress will hit 100%
+        // until then we need to interpolate between last position
+        // and front
+        let progress = progress - 1.0;
+
+        Point2f::new(self.front.x as f32 + dir_x * progress,
+                     self.front.y as f32 + dir_y * progress,
+        )
+    }
+
+    pub fn interpolated_tail(&self, progress: f32) -> Option<Point2f> {
+        self.body.front().map(|tail| {
+            let dir_x = tail.1.x as f32;
+            let dir_y = tail.1.y as f32;
+            // the tail only moves if the snake is not growing
+            let progress = if self.is_alive() && self.body.len() == self.length {
+                progress
+            } else {
+                0.0
+            };
+
+            Point2f::new(tail.0.x as f32 + dir_x * progress,
+                         tail.0.y as f32 + dir_y * progress,
+            )
+        })
+    }
+
+    pub(crate) fn enqueue_dir(&mut self, dir: Direction) {
+        self.dir_buffer.enqueue_dir(dir);
+    }
+
+    /// Takes the next buffered direction, returns whether the snake turned.
+    pub(crate) fn apply_next_dir(&mut self) -> bool {
+        match self.dir_buffer.next_dir() {
+            // prevent self collision by pressing reverse direction
+            Some(dir) if dir != self.dir && dir != self.dir.opposite() => {
+                self.dir = dir;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub(crate) fn set_dir(&mut self, dir: Direction) {
+        self.dir = dir;
+    }
+
+    /// Moves the current head into the body, `move_to` has to follow to place the new head.
+    pub(crate) fn push_head(&mut self) {
+        self.body.push_back((self.front, self.dir.vector()));
+    }
+
+    pub(crate) fn move_to(&mut self, front: Point2i) {
+        self.front = front;
+    }
+
+    /// Applies the effect of eating `kind`, `trim_tail` has to follow when the snake shrinks.
+    pub(crate) fn eat(&mut self, kind: FoodKind) {
+        self.score += kind.points();
+        self.length = (self.length as i32 + kind.growth()).max(1) as usize;
+    }
+
+    /// Drops the segments exceeding the length of the snake and returns their cells.
+    pub(crate) fn trim_tail(&mut self) -> Vec<Point2i> {
+        let excess = self.body.len().saturating_sub(self.length);
+        self.body.drain(..excess).map(|segment| segment.0).collect()
+    }
+
+    /// Kills the snake in grid step `step`, undoing a pending `push_head`.
+    pub(crate) fn kill(&mut self, step: usize) {
+        self.body.pop_back();
+        self.die(step);
+    }
+
+    /// Kills the snake in grid step `step` where it is.
+    pub(crate) fn die(&mut self, step: usize) {
+        self.died_at = Some(step);
+    }
+
+    /// Drops every segment from the tail up to and including the last one at `p`.
+    /// The snake keeps at least one cell of length. Returns the cells of the dropped segments.
+    pub(crate) fn cut(&mut self, p: Point2i) -> Vec<Point2i> {
+        match self.body.iter().rposition(|segment| segment.0 == p) {
+            Some(index) => {
+                let dropped = self.body.drain(..=index).map(|segment| segment.0).collect();
+                self.length = self.body.len().max(1);
+                dropped
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Turns the snake around, the tail becomes the new head.
+    pub(crate) fn reverse(&mut self) {
+        let dirs: Vec<Point2i> = self.body.iter().map(|segment| segment.1).collect();
+        let mut positions: Vec<Point2i> = self.body.iter().map(|segment| segment.0).collect();
+        positions.push(self.front);
+
+        if dirs.is_empty() {
+            self.dir = self.dir.opposite();
+            return;
+        }
+
+        // positions[i] moved in dirs[i] to reach positions[i + 1]
+        let reversed = |v: Point2i| Point2i::new(-v.x, -v.y);
+        self.body = (0..dirs.len()).rev().map(|i| (positions[i + 1], reversed(dirs[i]))).collect();
+        self.front = positions[0];
+        self.dir = Direction::from_vector(reversed(dirs[0])).unwrap_or_else(|| self.dir.opposite());
+    }
+}
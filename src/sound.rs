@@ -1,4 +1,5 @@
-use crate::engine::GameEvent;
+use rust_snake::engine::GameEvent;
+use rust_snake::food::FoodKind;
 
 use rodio::{Decoder, OutputStreamHandle, OutputStream, source::Source};
 use rodio::source::{Buffered, SamplesConverter};
@@ -44,26 +45,54 @@ impl Sound {
         let eat_source = Decoder::new(eat_file).unwrap();
         let eat_sample = eat_source.convert_samples().buffered();
 
-        return Sound {
+        Sound {
             _stream,
             stream_handle,
             turn_sample,
             collision_sample,
             teleport_sample,
             eat_sample,
-        };
+        }
     }
 
-    pub fn play_for_event(&self, ev: &GameEvent) {
-        match ev {
-            GameEvent::None => {}
-            GameEvent::Turn => { self.play_sample(self.turn_sample.clone()); }
-            GameEvent::Teleport => { self.play_sample(self.teleport_sample.clone()); }
-            GameEvent::Eat => { self.play_sample(self.eat_sample.clone()); }
-            GameEvent::Collision => {
-                self.play_sample(self.collision_sample.clone());
+    /// Plays the sounds for the events of one step, every sample at most once.
+    pub fn play_for_events(&self, events: &[GameEvent]) {
+        let mut turn = false;
+        let mut teleport = false;
+        let mut eaten: Vec<FoodKind> = Vec::new();
+        let mut collision = false;
+
+        for ev in events {
+            match ev {
+                GameEvent::Turn { .. } | GameEvent::Bounce { .. } => turn = true,
+                GameEvent::Teleport { .. } => teleport = true,
+                GameEvent::Eat { kind, .. } if !eaten.contains(kind) => eaten.push(*kind),
+                GameEvent::Death { .. } | GameEvent::Cut { .. } => collision = true,
+                _ => {}
             }
         }
+
+        if turn { self.play_sample(self.turn_sample.clone()); }
+        if teleport { self.play_sample(self.teleport_sample.clone()); }
+        for kind in eaten {
+            // every kind of food has its own pitch of the eat sound
+            self.play_sample(self.eat_sample.clone().speed(Sound::eat_pitch(kind)));
+        }
+        if collision {
+            self.play_sample(self.collision_sample.clone());
+        }
+    }
+
+    fn eat_pitch(kind: FoodKind) -> f32 {
+        match kind {
+            FoodKind::Normal => 1.0,
+            FoodKind::Bonus => 1.25,
+            FoodKind::Shrink => 0.8,
+            FoodKind::Poison => 0.6,
+            FoodKind::SpeedUp => 1.5,
+            FoodKind::SlowDown => 0.7,
+            FoodKind::Golden => 2.0,
+        }
     }
 
     fn play_sample<S>(&self, sample: S)
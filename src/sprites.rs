@@ -0,0 +1,121 @@
+//! Textured rendering from the sprite atlas `assets/sprites.png`.
+//!
+//! The atlas is a grid of square sprites in the order of `Sprite`. Sprites are white or grey and
+//! get tinted with the colour they are drawn with, directed sprites face right. Without an atlas
+//! every sprite falls back to the plain rectangle look.
+
+use std::path::Path;
+
+use piston_window::*;
+use rust_snake::engine::Direction;
+
+
+/// Edge length of a sprite in the atlas, in pixels.
+const TILE_SIZE: f64 = 32.0;
+const ATLAS_COLUMNS: usize = 8;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Sprite {
+    Head,
+    /// Body segment connecting the left and right edge.
+    Straight,
+    /// Body segment connecting the left and bottom edge.
+    Corner,
+    Tail,
+    /// Special food.
+    Food,
+    /// Normal food, the Rust logo.
+    Logo,
+    Wall,
+    Door,
+    OpenDoor,
+    Teleporter,
+    /// Teleporter cell which only receives the snake.
+    TeleporterExit,
+    Switch,
+    Plate,
+    Key,
+}
+
+impl Sprite {
+    fn source(self) -> [f64; 4] {
+        let index = self as usize;
+        [(index % ATLAS_COLUMNS) as f64 * TILE_SIZE, (index / ATLAS_COLUMNS) as f64 * TILE_SIZE, TILE_SIZE, TILE_SIZE]
+    }
+
+    /// Draws the sprite in the rectangle look, used without an atlas.
+    fn draw_fallback(self, color: [f32; 4], rect: [f64; 4], c: &Context, g: &mut G2d) {
+        match self {
+            Sprite::Food => ellipse(color, rect, c.transform, g),
+            Sprite::Key => ellipse(color, inset(rect, rect[2] / 6.0), c.transform, g),
+            Sprite::Switch => rectangle(color, inset(rect, rect[2] / 4.0), c.transform, g),
+            Sprite::TeleporterExit => {
+                let border = rect[2] / 5.0;
+                Rectangle::new_border(color, border / 2.0).draw(inset(rect, border / 2.0), &c.draw_state, c.transform, g);
+            }
+            _ => rectangle(color, rect, c.transform, g),
+        }
+    }
+}
+
+fn inset(rect: [f64; 4], by: f64) -> [f64; 4] {
+    [rect[0] + by, rect[1] + by, rect[2] - 2.0 * by, rect[3] - 2.0 * by]
+}
+
+/// Clockwise rotation turning a sprite that faces right towards `dir`.
+fn angle(dir: Direction) -> f64 {
+    match dir {
+        Direction::Right => 0.0,
+        Direction::Down => 90.0,
+        Direction::Left => 180.0,
+        Direction::Up => 270.0,
+    }
+}
+
+/// The body sprite and its direction for a segment the snake entered moving in `entered`
+/// and left moving in `left`.
+pub fn segment(entered: Direction, left: Direction) -> (Sprite, Direction) {
+    if entered == left || entered == left.opposite() {
+        return (Sprite::Straight, left);
+    }
+
+    // the corner sprite connects the left and bottom edge when facing right,
+    // every quarter turn moves both edges on clockwise
+    let edges = [entered.opposite(), left];
+    let mut facing = Direction::Right;
+    let mut corner = [Direction::Left, Direction::Down];
+    while !(edges.contains(&corner[0]) && edges.contains(&corner[1])) {
+        facing = facing.turn_right();
+        corner = [corner[0].turn_right(), corner[1].turn_right()];
+    }
+    (Sprite::Corner, facing)
+}
+
+
+pub struct SpriteAtlas {
+    texture: G2dTexture,
+}
+
+impl SpriteAtlas {
+    pub fn load<P: AsRef<Path>>(window: &mut PistonWindow, path: P) -> Result<SpriteAtlas, String> {
+        let texture = Texture::from_path(&mut window.create_texture_context(), path, Flip::None, &TextureSettings::new())?;
+        Ok(SpriteAtlas { texture })
+    }
+}
+
+/// Draws `sprite` tinted with `color` into the square `rect`, facing `dir`.
+/// Draws the rectangle look if there is no atlas.
+pub fn draw_sprite(atlas: Option<&SpriteAtlas>, sprite: Sprite, color: [f32; 4], rect: [f64; 4], dir: Direction,
+                   c: &Context, g: &mut G2d) {
+    let atlas = match atlas {
+        Some(atlas) => atlas,
+        None => return sprite.draw_fallback(color, rect, c, g),
+    };
+
+    let half = rect[2] / 2.0;
+    let transform = c.transform.trans(rect[0] + half, rect[1] + half).rot_deg(angle(dir)).trans(-half, -half);
+    Image::new_color(color)
+        .src_rect(sprite.source())
+        .rect([0.0, 0.0, rect[2], rect[3]])
+        .draw(&atlas.texture, &c.draw_state, transform, g);
+}
@@ -1,6 +1,9 @@
 use piston_window::math::Scalar;
 use piston_window::*;
 
+/// Window size the text screens are laid out for, the classic board at 20 pixels per cell.
+const REFERENCE_SIZE: [f64; 2] = [400.0, 400.0];
+
 fn text_size<C>(
     font_size: types::FontSize,
     text: &str,
@@ -74,5 +77,42 @@ pub fn draw_text_multiline<C, G>(color: types::Color,
         C: character::CharacterCache,
         G: Graphics<Texture=<C as character::CharacterCache>::Texture>
 {
-    draw_text_lines(color, font_size, &text.lines().collect::<Vec<&str>>(), area_size, cache, transform, g)
+    let lines = text.lines().collect::<Vec<&str>>();
+    let font_size = fitting_font_size(font_size, &lines, area_size, cache)?;
+    draw_text_lines(color, font_size, &lines, area_size, cache, transform, g)
+}
+
+/// The largest font size up to `font_size` at which every line fits into the width of the area.
+fn fitting_font_size<C>(font_size: types::FontSize,
+                        lines: &[&str],
+                        area_size: Size,
+                        cache: &mut C,
+) -> Result<types::FontSize, C::Error>
+    where
+        C: character::CharacterCache,
+{
+    let mut font_size = font_size;
+    while font_size > 1 {
+        let mut width: Scalar = 0.0;
+        for &line in lines {
+            width = width.max(text_size(font_size, line, cache)?.width);
+        }
+        if width <= area_size.width {
+            break;
+        }
+        // glyph widths grow about linearly with the font size
+        let shrunk = (font_size as f64 * area_size.width / width) as types::FontSize;
+        font_size = shrunk.min(font_size - 1).max(1);
+    }
+    Ok(font_size)
+}
+
+/// Scale of text and layout on screens designed for a window of `REFERENCE_SIZE`.
+pub fn screen_scale(view_size: [f64; 2]) -> f64 {
+    (view_size[0] / REFERENCE_SIZE[0]).min(view_size[1] / REFERENCE_SIZE[1])
+}
+
+/// `font_size` scaled by `scale`, at least 1.
+pub fn scaled(font_size: types::FontSize, scale: f64) -> types::FontSize {
+    ((font_size as f64 * scale).round() as types::FontSize).max(1)
 }
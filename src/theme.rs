@@ -0,0 +1,331 @@
+//! Colours, sprites and font of the game window.
+//!
+//! Themes are plain-text files in `assets/themes` with one `key: value` entry per line.
+//! Every entry is optional, missing entries keep the look of the default light theme:
+//!
+//! ```text
+//! # comments and empty lines are allowed
+//! name: Dark
+//! background: #1e1f24
+//! player 1: #ff5c5c #d12c2c
+//! teleporters: #4d6bff #33b3d9
+//! sprites: none
+//! ```
+//!
+//! Colours are written as `#rrggbb` or `#rrggbbaa`. Entries:
+//!
+//! * `name: <text>` shown in the settings
+//! * `font: <file>` font in the assets folder
+//! * `sprites: <file|none>` sprite atlas in the assets folder, `none` draws plain rectangles
+//! * `background`, `text`, `hint`, `highlight`, `win`, `lose`: colours of the board and the screen texts
+//! * `overlay` veil drawn over the computer game behind the main menu
+//! * `letterbox` bars beside the board when the window has a different shape
+//! * `hud`, `hud label`, `hud text`: background, labels and values of the panel with scores and timers
+//! * `hud position: <top|bottom|left|right>` side of the window the panel is placed on
+//! * `hud font size: <pixels>`
+//! * `player <number>: <body> <head>` colours of the snakes of players 1 to 4
+//! * `dead` colour of dead snakes
+//! * `food <kind>` colour of every food kind, e.g. `food golden: #ffcc00`
+//! * `wall`, `door`, `door open`, `door closing`, `switch`, `plate`, `plate pressed`, `key`
+//! * `teleporters: <colour> ...` colours of linked teleporters, used in turn
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use piston_window::{Glyphs, PistonWindow};
+use rust_snake::food::FoodKind;
+use rust_snake::parse::{parse_error, tokens, ParseError};
+
+use crate::sprites::SpriteAtlas;
+
+
+pub type Color = [f32; 4];
+
+/// Where the HUD is placed, the board gets the rest of the window.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum HudPosition {
+    Top,
+    Bottom,
+    /// A column beside the board, one item per line.
+    Left,
+    Right,
+}
+
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub name: String,
+    pub font: String,
+    pub sprites: Option<String>,
+    pub background: Color,
+    pub text: Color,
+    /// Less important text like the key hints of the main menu.
+    pub hint: Color,
+    /// Text asking for input and the selected entry of a list.
+    pub highlight: Color,
+    pub win: Color,
+    pub lose: Color,
+    pub overlay: Color,
+    pub letterbox: Color,
+    pub hud: Color,
+    pub hud_label: Color,
+    pub hud_text: Color,
+    pub hud_position: HudPosition,
+    pub hud_font_size: u32,
+    /// Body and head colour of every player.
+    pub players: [(Color, Color); 4],
+    pub dead: Color,
+    /// Colour of every food kind, in the order of `FoodKind::ALL`.
+    pub food: [Color; 7],
+    pub wall: Color,
+    pub door: Color,
+    pub door_open: Color,
+    /// Open doors about to close flash in this colour.
+    pub door_closing: Color,
+    pub switch: Color,
+    pub plate: Color,
+    pub plate_pressed: Color,
+    pub key: Color,
+    /// Linked teleporter cells share one of these colours.
+    pub teleporters: Vec<Color>,
+}
+
+impl Default for Theme {
+    /// The light theme the game always had.
+    fn default() -> Theme {
+        Theme {
+            name: "Light".to_string(),
+            font: "FiraSans-Regular.ttf".to_string(),
+            sprites: Some("sprites.png".to_string()),
+            background: [0.95, 0.95, 0.95, 1.0],
+            text: [0.0, 0.0, 0.0, 1.0],
+            hint: [0.3, 0.3, 0.3, 1.0],
+            highlight: [0.0, 0.8, 0.0, 1.0],
+            win: [0.0, 0.6, 0.0, 1.0],
+            lose: [0.8, 0.0, 0.0, 1.0],
+            overlay: [0.95, 0.95, 0.95, 0.8],
+            letterbox: [0.8, 0.8, 0.8, 1.0],
+            hud: [0.85, 0.85, 0.85, 1.0],
+            hud_label: [0.4, 0.4, 0.4, 1.0],
+            hud_text: [0.0, 0.0, 0.0, 1.0],
+            hud_position: HudPosition::Top,
+            hud_font_size: 14,
+            players: [
+                ([1.0, 0.2, 0.2, 1.0], [0.8, 0.0, 0.0, 1.0]), // red
+                ([0.2, 0.4, 1.0, 1.0], [0.0, 0.2, 0.8, 1.0]), // blue
+                ([1.0, 0.6, 0.1, 1.0], [0.8, 0.4, 0.0, 1.0]), // orange
+                ([0.7, 0.3, 0.9, 1.0], [0.5, 0.1, 0.7, 1.0]), // purple
+            ],
+            dead: [0.6, 0.6, 0.6, 1.0],
+            food: [
+                [0.1, 0.8, 0.1, 1.0], // normal: green
+                [0.0, 0.6, 0.6, 1.0], // bonus: teal
+                [0.9, 0.4, 0.7, 1.0], // shrink: pink
+                [0.3, 0.1, 0.1, 1.0], // poison: dark brown
+                [1.0, 0.1, 0.1, 1.0], // speed-up: bright red
+                [0.5, 0.7, 1.0, 1.0], // slow-down: light blue
+                [1.0, 0.8, 0.0, 1.0], // golden: gold
+            ],
+            wall: [0.3, 0.3, 0.3, 1.0],
+            door: [0.4, 0.4, 0.4, 1.0],
+            door_open: [0.9, 0.9, 0.9, 1.0],
+            door_closing: [0.95, 0.65, 0.3, 1.0],
+            switch: [0.85, 0.55, 0.1, 1.0],
+            plate: [0.8, 0.75, 0.55, 1.0],
+            plate_pressed: [0.55, 0.5, 0.35, 1.0],
+            key: [0.6, 0.3, 0.8, 1.0],
+            teleporters: vec![
+                [0.1, 0.1, 0.9, 1.0], // blue
+                [0.0, 0.6, 0.8, 1.0], // cyan
+                [0.8, 0.1, 0.6, 1.0], // magenta
+                [0.1, 0.5, 0.4, 1.0], // dark teal
+                [0.35, 0.2, 0.7, 1.0], // indigo
+                [0.2, 0.3, 0.5, 1.0], // slate
+            ],
+        }
+    }
+}
+
+/// Parses `#rrggbb` or `#rrggbbaa` found at `column`.
+fn parse_color(line: usize, (column, token): (usize, &str)) -> Result<Color, ParseError> {
+    let digits = token.strip_prefix('#').filter(|d| (d.len() == 6 || d.len() == 8) && d.is_ascii());
+    let channels: Option<Vec<f32>> = digits.and_then(|d| {
+        (0..d.len()).step_by(2).map(|i| u8::from_str_radix(&d[i..i + 2], 16).ok().map(|c| c as f32 / 255.0)).collect()
+    });
+
+    match channels.as_deref() {
+        Some([r, g, b]) => Ok([*r, *g, *b, 1.0]),
+        Some([r, g, b, a]) => Ok([*r, *g, *b, *a]),
+        _ => parse_error(line, column, format!("expected a colour like #1e1f24, found '{}'", token)),
+    }
+}
+
+impl Theme {
+    pub fn parse(source: &str) -> Result<Theme, ParseError> {
+        let mut theme = Theme::default();
+
+        for (i, line) in source.lines().enumerate() {
+            let line_no = i + 1;
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            let colon = match line.find(':') {
+                Some(c) => c,
+                None => return parse_error(line_no, line.len() - line.trim_start().len() + 1,
+                                           "expected 'key: value'".to_string()),
+            };
+            let key = line[..colon].trim();
+            let key_column = line.len() - line.trim_start().len() + 1;
+            let text = line[colon + 1..].trim();
+            let values = tokens(line, colon + 1);
+
+            let single = || match values[..] {
+                [value] => Ok(value),
+                _ => parse_error(line_no, values.get(1).map_or(colon + 2, |v| v.0),
+                                 format!("'{}' expects 1 value, found {}", key, values.len())),
+            };
+            let color = || single().and_then(|value| parse_color(line_no, value));
+
+            match key {
+                "name" => theme.name = text.to_string(),
+                "font" => theme.font = single()?.1.to_string(),
+                "sprites" => theme.sprites = Some(single()?.1).filter(|s| *s != "none").map(str::to_string),
+                "background" => theme.background = color()?,
+                "text" => theme.text = color()?,
+                "hint" => theme.hint = color()?,
+                "highlight" => theme.highlight = color()?,
+                "win" => theme.win = color()?,
+                "lose" => theme.lose = color()?,
+                "overlay" => theme.overlay = color()?,
+                "letterbox" => theme.letterbox = color()?,
+                "hud" => theme.hud = color()?,
+                "hud label" => theme.hud_label = color()?,
+                "hud text" => theme.hud_text = color()?,
+                "hud position" => {
+                    let value = single()?;
+                    theme.hud_position = match value.1 {
+                        "top" => HudPosition::Top,
+                        "bottom" => HudPosition::Bottom,
+                        "left" => HudPosition::Left,
+                        "right" => HudPosition::Right,
+                        other => return parse_error(line_no, value.0,
+                                                    format!("expected top, bottom, left or right, found '{}'", other)),
+                    };
+                }
+                "hud font size" => {
+                    let value = single()?;
+                    theme.hud_font_size = match value.1.parse() {
+                        Ok(size) if size > 0 => size,
+                        _ => return parse_error(line_no, value.0, format!("expected a font size, found '{}'", value.1)),
+                    };
+                }
+                "dead" => theme.dead = color()?,
+                "wall" => theme.wall = color()?,
+                "door" => theme.door = color()?,
+                "door open" => theme.door_open = color()?,
+                "door closing" => theme.door_closing = color()?,
+                "switch" => theme.switch = color()?,
+                "plate" => theme.plate = color()?,
+                "plate pressed" => theme.plate_pressed = color()?,
+                "key" => theme.key = color()?,
+                "teleporters" => {
+                    if values.is_empty() {
+                        return parse_error(line_no, colon + 2, "'teleporters' expects at least 1 colour".to_string());
+                    }
+                    theme.teleporters = values.iter().map(|v| parse_color(line_no, *v)).collect::<Result<_, _>>()?;
+                }
+                _ if key.starts_with("player ") => {
+                    let player = match key["player ".len()..].trim().parse::<usize>() {
+                        Ok(n) if (1..=theme.players.len()).contains(&n) => n - 1,
+                        _ => return parse_error(line_no, key_column,
+                                                format!("expected a player number from 1 to {} in '{}'", theme.players.len(), key)),
+                    };
+                    match values[..] {
+                        [body, head] => theme.players[player] = (parse_color(line_no, body)?, parse_color(line_no, head)?),
+                        _ => return parse_error(line_no, colon + 2,
+                                                format!("'{}' expects a body and a head colour", key)),
+                    }
+                }
+                _ if key.starts_with("food ") => {
+                    let name = key["food ".len()..].trim();
+                    match FoodKind::ALL.iter().position(|kind| kind.name() == name) {
+                        Some(index) => theme.food[index] = color()?,
+                        None => return parse_error(line_no, key_column, format!("unknown food kind '{}'", name)),
+                    }
+                }
+                _ => return parse_error(line_no, key_column, format!("unknown key '{}'", key)),
+            }
+        }
+
+        Ok(theme)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Theme, ParseError> {
+        Theme::parse(&fs::read_to_string(path)?)
+    }
+
+    /// The default theme followed by all themes in `dir`, sorted by file name.
+    /// Files that fail to load are reported and skipped.
+    pub fn load_all<P: AsRef<Path>>(dir: P) -> Vec<Theme> {
+        let mut paths: Vec<PathBuf> = match fs::read_dir(&dir) {
+            Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|ext| ext == "theme"))
+                .collect(),
+            Err(err) => {
+                eprintln!("Failed to read themes from {}: {}", dir.as_ref().display(), err);
+                Vec::new()
+            }
+        };
+        paths.sort();
+
+        let mut themes = vec![Theme::default()];
+        for path in paths {
+            match Theme::from_file(&path) {
+                Ok(theme) => themes.push(theme),
+                Err(err) => eprintln!("Failed to load theme {}: {}", path.display(), err),
+            }
+        }
+        themes
+    }
+
+    pub fn food_color(&self, kind: FoodKind) -> Color {
+        self.food[FoodKind::ALL.iter().position(|k| *k == kind).unwrap_or(0)]
+    }
+
+    pub fn player_colors(&self, player: usize) -> (Color, Color) {
+        self.players[player % self.players.len()]
+    }
+
+    pub fn teleporter_color(&self, link: usize) -> Color {
+        self.teleporters[link % self.teleporters.len()]
+    }
+}
+
+
+/// A theme along with its loaded font and sprites, everything needed to draw a screen.
+pub struct Skin {
+    pub theme: Theme,
+    pub glyphs: Glyphs,
+    pub sprites: Option<SpriteAtlas>,
+}
+
+impl Skin {
+    /// Loads the font and sprites of `theme` from `assets`. A font that fails to load is replaced by
+    /// the default font, sprites that fail to load by rectangles. `rectangles` skips the sprites.
+    pub fn load(window: &mut PistonWindow, assets: &Path, theme: Theme, rectangles: bool) -> Skin {
+        let glyphs = window.load_font(assets.join(&theme.font)).unwrap_or_else(|err| {
+            eprintln!("Failed to load font {}, using the default font: {}", theme.font, err);
+            window.load_font(assets.join(Theme::default().font)).unwrap()
+        });
+
+        let sprites = match &theme.sprites {
+            Some(file) if !rectangles => SpriteAtlas::load(window, assets.join(file))
+                .map_err(|err| eprintln!("Failed to load sprites {}, drawing rectangles instead: {}", file, err))
+                .ok(),
+            _ => None,
+        };
+
+        Skin { theme, glyphs, sprites }
+    }
+}
@@ -0,0 +1,139 @@
+//! Where the board appears in the window.
+
+use piston_window::*;
+
+
+/// Cells are never drawn smaller than this, larger boards scroll instead.
+const MIN_CELL_SIZE: f64 = 16.0;
+/// The camera only moves once its target leaves the middle part of the window,
+/// this fraction of the visible area on every side is outside of that dead-zone.
+const DEAD_ZONE_MARGIN: f64 = 0.3;
+/// How quickly the camera catches up with its target, the share of the distance left after
+/// one second is `exp(-FOLLOW_RATE)`.
+const FOLLOW_RATE: f64 = 6.0;
+
+
+/// Which part of a board is shown when it does not fit into the window, in cells.
+#[derive(Copy, Clone, Debug)]
+pub struct Camera {
+    /// Board position in the middle of the window.
+    center: [f64; 2],
+    board: [f64; 2],
+    /// Number of cells fitting into the window, as of the last frame.
+    visible: [f64; 2],
+}
+
+impl Camera {
+    /// A camera over a board of `width` x `height` cells, looking at `target`.
+    pub fn new(width: i32, height: i32, target: [f64; 2]) -> Camera {
+        // the target is kept as it is until the first frame tells how much of the board fits
+        let board = [width as f64, height as f64];
+        Camera { center: target, board, visible: board }
+    }
+
+    /// Moves the camera `dt` seconds closer to `target`. The camera stays put while the target
+    /// is inside the dead-zone and then follows it smoothly.
+    pub fn follow(&mut self, target: [f64; 2], dt: f64) {
+        let catch_up = 1.0 - (-FOLLOW_RATE * dt).exp();
+        for (axis, target) in target.iter().enumerate() {
+            let dead_zone = self.visible[axis] * (0.5 - DEAD_ZONE_MARGIN);
+            let offset = target - self.center[axis];
+            let outside = offset - offset.clamp(-dead_zone, dead_zone);
+            self.center[axis] += outside * catch_up;
+        }
+        self.clamp();
+    }
+
+    fn set_visible(&mut self, visible: [f64; 2]) {
+        self.visible = visible;
+        self.clamp();
+    }
+
+    /// Keeps the view inside of the board, boards smaller than the view stay centered.
+    fn clamp(&mut self) {
+        for axis in 0..2 {
+            let half = self.visible[axis] / 2.0;
+            self.center[axis] = if self.visible[axis] >= self.board[axis] {
+                self.board[axis] / 2.0
+            } else {
+                self.center[axis].clamp(half, self.board[axis] - half)
+            };
+        }
+    }
+
+    /// Board position shown in the top left corner of the view.
+    fn corner(&self) -> [f64; 2] {
+        [self.center[0] - self.visible[0] / 2.0, self.center[1] - self.visible[1] / 2.0]
+    }
+}
+
+
+/// The board scaled to fit into its part of the window and centered in it. The space left on the sides
+/// that are too long for the board is covered by bars. Boards which would need cells smaller
+/// than `MIN_CELL_SIZE` only show the part around the camera.
+#[derive(Copy, Clone, Debug)]
+pub struct BoardView {
+    /// Edge length of a cell in pixels, whole pixels to keep neighbouring cells seamless.
+    pub cell_size: f64,
+    /// Window position of the top left corner of the visible part of the board.
+    pub origin: [f64; 2],
+    /// Size of the visible part of the board in pixels.
+    pub size: [f64; 2],
+    /// Pixels of the board scrolled out of view on the top and left.
+    scroll: [f64; 2],
+    board: [f64; 2],
+    /// The part of the window reserved for the board, as `[x, y, width, height]`.
+    pub area: [f64; 4],
+}
+
+impl BoardView {
+    /// Fits a board of `width` x `height` cells into `area` of the window
+    /// and lets the camera know how much of the board is visible.
+    pub fn new(area: [f64; 4], width: i32, height: i32, camera: &mut Camera) -> BoardView {
+        let board = [width as f64, height as f64];
+        let fitting = (area[2] / board[0]).min(area[3] / board[1]).floor();
+        let cell_size = fitting.max(MIN_CELL_SIZE);
+
+        let size = [(board[0] * cell_size).min(area[2]), (board[1] * cell_size).min(area[3])];
+        camera.set_visible([size[0] / cell_size, size[1] / cell_size]);
+
+        let corner = camera.corner();
+        let scroll = [(corner[0] * cell_size).round(), (corner[1] * cell_size).round()];
+        let origin = [(area[0] + (area[2] - size[0]) / 2.0).round(), (area[1] + (area[3] - size[1]) / 2.0).round()];
+        BoardView { cell_size, origin, size, scroll, board, area }
+    }
+
+    /// The rectangle covered by the cell at `x`, `y`. Fractional positions are used for moving parts.
+    pub fn cell(&self, x: f64, y: f64) -> [f64; 4] {
+        [self.origin[0] + x * self.cell_size - self.scroll[0], self.origin[1] + y * self.cell_size - self.scroll[1],
+         self.cell_size, self.cell_size]
+    }
+
+    /// Whether only a part of the board is shown.
+    pub fn scrolls(&self) -> bool {
+        self.size[0] < self.board[0] * self.cell_size || self.size[1] < self.board[1] * self.cell_size
+    }
+
+    /// The visible part of the board in cells, as `[x, y, width, height]`.
+    pub fn visible_cells(&self) -> [f64; 4] {
+        [self.scroll[0] / self.cell_size, self.scroll[1] / self.cell_size,
+         self.size[0] / self.cell_size, self.size[1] / self.cell_size]
+    }
+
+    /// Covers the rest of the area, including parts of the snakes moving across the edge of the board.
+    pub fn draw_bars(&self, color: [f32; 4], c: &Context, g: &mut G2d) {
+        let [x, y] = self.origin;
+        let [width, height] = self.size;
+        let [left, top, area_width, area_height] = self.area;
+        let (right, bottom) = (left + area_width, top + area_height);
+        let bars = [
+            [left, top, area_width, y - top],
+            [left, y + height, area_width, bottom - y - height],
+            [left, y, x - left, height],
+            [x + width, y, right - x - width, height],
+        ];
+        for bar in bars.iter().filter(|bar| bar[2] > 0.0 && bar[3] > 0.0) {
+            rectangle(color, *bar, c.transform, g);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Doors closing while snakes move through them.
+
+use rust_snake::engine::{Game, GameEvent};
+use rust_snake::level::Level;
+
+fn game(source: &str) -> Game {
+    Game::with_seed(&Level::parse(source).unwrap(), 0)
+}
+
+/// Advances the game by exactly one grid step.
+fn step(game: &mut Game) -> Vec<GameEvent> {
+    game.make_step(game.step_time() as f64)
+}
+
+/// Runs `steps` steps and returns all events, failing on any death.
+fn run_without_deaths(game: &mut Game, steps: usize) -> Vec<GameEvent> {
+    let mut all = Vec::new();
+    for _ in 0..steps {
+        let events = step(game);
+        assert!(!events.iter().any(|e| matches!(e, GameEvent::Death { .. })), "{:?}", events);
+        all.extend(events);
+    }
+    all
+}
+
+/// The snake in the top row enters the waiting door C in the third step, in the same step
+/// the snake in the bottom row enters the second switch and signals the door to close.
+const SWITCHED_WHILE_ENTERING: &str = "\
+snake: right 1
+door: C switch closing wait
+switch: ! C
+---
+{top}..C....
+........
+{bottom}!.!....
+";
+
+fn switched_while_entering(top: char, bottom: char) -> Game {
+    game(&SWITCHED_WHILE_ENTERING.replace("{top}", &top.to_string()).replace("{bottom}", &bottom.to_string()))
+}
+
+fn assert_door_waits(mut game: Game, entering: usize) {
+    let events = run_without_deaths(&mut game, 3);
+    assert_eq!(events.iter().filter(|e| matches!(e, GameEvent::DoorToggled { open: true, .. })).count(), 1);
+    assert_eq!(game.snakes[entering].head().x, 3);
+
+    // the door closes once the snake has passed it
+    let events = run_without_deaths(&mut game, 3);
+    assert!(events.iter().any(|e| matches!(e, GameEvent::DoorToggled { open: false, .. })), "{:?}", events);
+    assert!(game.snakes.iter().all(|snake| snake.is_alive()));
+}
+
+#[test]
+fn waiting_door_holds_for_a_head_entering_while_switched() {
+    assert_door_waits(switched_while_entering('@', '2'), 0);
+}
+
+#[test]
+fn waiting_door_holds_no_matter_which_snake_moves_first() {
+    assert_door_waits(switched_while_entering('2', '@'), 1);
+}
+
+#[test]
+fn food_doors_without_a_count_start_open() {
+    let mut open = game("snake: right 1\ndoor: A food 0\n---\n@AA.\n....\n");
+    run_without_deaths(&mut open, 3);
+
+    let mut closed = game("snake: right 1\ndoor: A food 1\n---\n@AA.\n....\n");
+    assert!(step(&mut closed).iter().any(|e| matches!(e, GameEvent::Death { player: 0, .. })));
+}
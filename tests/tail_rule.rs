@@ -0,0 +1,120 @@
+//! Heads moving into the cell a tail leaves in the same grid step.
+
+use rust_snake::engine::{DeathCause, Direction, Game, GameEvent, Input, TailRule};
+use rust_snake::level::Level;
+
+/// Clockwise around a 2x2 block, starting to the right.
+const LOOP: [Direction; 4] = [Direction::Right, Direction::Down, Direction::Left, Direction::Up];
+
+fn game(source: &str) -> Game {
+    Game::with_seed(&Level::parse(source).unwrap(), 0)
+}
+
+/// Advances the game by exactly one grid step.
+fn step(game: &mut Game) -> Vec<GameEvent> {
+    game.make_step(game.step_time() as f64)
+}
+
+/// A single snake circling a 2x2 block, which enters the cell of its tail from the fourth step on.
+fn circling(tail: &str, length: usize, food: (usize, usize)) -> Game {
+    let mut grid = vec![vec!['.'; 6]; 5];
+    grid[2][2] = '@';
+    grid[food.1][food.0] = '*';
+    let grid: Vec<String> = grid.into_iter().map(|row| row.into_iter().collect()).collect();
+    game(&format!("snake: right {}\ntail: {}\n---\n{}\n", length, tail, grid.join("\n")))
+}
+
+/// Runs the circling snake for `steps` steps, returns the step it died in and why.
+fn circle(game: &mut Game, steps: usize) -> Option<(usize, DeathCause)> {
+    for i in 0..steps {
+        game.handle_input(0, Input::Turn(LOOP[i % LOOP.len()]));
+        let death = step(game).into_iter().find_map(|event| match event {
+            GameEvent::Death { player: 0, cause } => Some(cause),
+            _ => None,
+        });
+        if let Some(cause) = death {
+            return Some((i + 1, cause));
+        }
+    }
+    None
+}
+
+#[test]
+fn follow_is_the_default() {
+    let level = Level::parse("snake: right 1\n---\n@..\n...\n..*\n").unwrap();
+    assert_eq!(level.tail, TailRule::Follow);
+    assert_eq!(Game::with_seed(&level, 0).tail(), TailRule::Follow);
+}
+
+#[test]
+fn chasing_own_tail_with_follow() {
+    let mut game = circling("follow", 3, (5, 4));
+    assert_eq!(circle(&mut game, 12), None);
+    assert!(game.snakes[0].is_alive());
+    assert_eq!(game.snakes[0].length(), 3);
+}
+
+#[test]
+fn chasing_own_tail_with_block() {
+    let mut game = circling("block", 3, (5, 4));
+    assert_eq!(circle(&mut game, 12), Some((4, DeathCause::OwnBody)));
+}
+
+#[test]
+fn growing_snake_keeps_its_tail() {
+    // a length of 4 does not fit into the 2x2 block, the tail has not moved yet in step 4
+    let mut game = circling("follow", 4, (5, 4));
+    assert_eq!(circle(&mut game, 12), Some((4, DeathCause::OwnBody)));
+}
+
+#[test]
+fn tail_stays_after_eating() {
+    // the food on the third cell of the loop makes the snake one cell longer right before it reaches its tail
+    let mut game = circling("follow", 3, (2, 3));
+    assert_eq!(circle(&mut game, 12), Some((4, DeathCause::OwnBody)));
+    assert_eq!(game.snakes[0].score(), 1);
+}
+
+/// Snakes start with their head only, the leading snake leaves its start cell in the second step,
+/// right when the follower enters it.
+#[test]
+fn following_another_snake() {
+    let mut game = game("snake: right 1\n---\n........\n.@.2....\n.......*\n");
+    for _ in 0..3 {
+        let events = step(&mut game);
+        assert!(!events.iter().any(|e| matches!(e, GameEvent::Death { .. })), "{:?}", events);
+    }
+    assert_eq!(game.snakes[0].head().x, 4);
+    assert_eq!(game.snakes[1].head().x, 6);
+}
+
+#[test]
+fn following_another_snake_with_block() {
+    let mut game = game("snake: right 1\ntail: block\n---\n........\n.@.2....\n.......*\n");
+    step(&mut game);
+    let events = step(&mut game);
+    assert!(events.contains(&GameEvent::Death { player: 0, cause: DeathCause::Snake(1) }), "{:?}", events);
+    assert!(game.snakes[1].is_alive());
+}
+
+#[test]
+fn following_a_snake_that_eats() {
+    // the leading snake eats in the same step and keeps its tail where the follower wants to go
+    let mut game = game("snake: right 1\n---\n........\n.@.2.*..\n........\n");
+    step(&mut game);
+    let events = step(&mut game);
+    assert!(events.contains(&GameEvent::Death { player: 0, cause: DeathCause::Snake(1) }), "{:?}", events);
+    assert_eq!(game.snakes[1].length(), 2);
+}
+
+#[test]
+fn tail_rule_is_kept_in_level_files_and_saves() {
+    let level = Level::parse("snake: right 1\ntail: block\n---\n@..\n...\n..*\n").unwrap();
+    assert_eq!(Level::parse(&level.to_string()).unwrap().tail, TailRule::Block);
+
+    let mut game = Game::with_seed(&level, 0);
+    game.set_tail(TailRule::Follow);
+    assert_eq!(Game::from_save(&game.save_state()).tail(), TailRule::Follow);
+
+    assert!(Level::parse("snake: right 1\ntail: sometimes\n---\n@..\n").is_err());
+}
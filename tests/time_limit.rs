@@ -0,0 +1,53 @@
+//! Games which end after the time limit of their level.
+
+use rust_snake::engine::Game;
+use rust_snake::level::Level;
+
+/// Snakes running along the rows of a board without borders, so they survive the time limit.
+fn game(snakes: &str) -> Game {
+    let level = format!("snake: right 2\nborder: wrap\ntime limit: 3\n---\n{}\n", snakes);
+    Game::with_seed(&Level::parse(&level).unwrap(), 0)
+}
+
+#[test]
+fn surviving_until_the_end_wins() {
+    let mut game = game("@.........\n..........\n..........");
+    game.make_step(1.0);
+    assert_eq!(game.time_left(), Some(2.0));
+    assert!(!game.is_over());
+
+    game.make_step(1.5);
+    assert_eq!(game.time_left(), Some(0.5));
+    game.make_step(1.0);
+    assert_eq!(game.time_left(), Some(0.0));
+    assert!(game.is_over() && game.is_won());
+
+    // the snakes stop where they were when the time ran out
+    let head = game.snakes[0].head();
+    let steps = game.step_count();
+    game.make_step(1.0);
+    assert_eq!((game.snakes[0].head(), game.step_count()), (head, steps));
+}
+
+#[test]
+fn the_highest_score_wins_when_the_time_is_up() {
+    let mut game = game("@.........\n..........\n.....2....");
+    while !game.is_over() {
+        game.make_step(0.5);
+    }
+    assert!(game.snakes.iter().all(|snake| snake.is_alive()));
+
+    let scores: Vec<usize> = game.snakes.iter().map(|snake| snake.score()).collect();
+    let best = *scores.iter().max().unwrap();
+    let expected = match scores.iter().filter(|score| **score == best).count() {
+        1 => scores.iter().position(|score| *score == best),
+        _ => None,
+    };
+    assert_eq!(game.winner(), expected);
+}
+
+#[test]
+fn levels_without_a_limit_are_not_timed() {
+    let game = Game::with_seed(&Level::classic(20, 20), 0);
+    assert_eq!(game.time_left(), None);
+}